@@ -1,9 +1,37 @@
 use core::time;
-
-use self::util::{apply_launcher_pr, apply_mods_pr, find_game_install_path};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use self::http::{HttpSettings, RetryPolicy};
+use self::operation::{format_bytes, RunningOperation};
+use self::util::{
+    apply_launcher_pr, apply_local_launcher_zip, apply_local_mods_zip, apply_mods_pr,
+    apply_mods_pr_selection, apply_release, apply_test_set, convert_localisation_files,
+    find_game_install_path, list_mods_pr,
+};
 use self_update::cargo_crate_version;
 
+mod cache;
+mod download;
+mod extract;
+mod filter;
+mod http;
+mod operation;
+mod preflight;
+mod staging;
 mod util;
+mod validate;
+
+/// Mods of a mods PR to pick from before installing only some of them
+#[derive(Debug, Clone)]
+struct ModSelection {
+    pr_number: i64,
+    pr_title: String,
+    /// PR list the PR was picked from, needed to install it later
+    json_response: serde_json::Value,
+    /// Names of the mods in the PR and whether they are picked
+    mods: Vec<(String, bool)>,
+}
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -23,6 +51,59 @@ pub struct TemplateApp {
     #[serde(skip)]
     json_response: serde_json::Value,
 
+    // Last fetched PR lists per repo, needed for test sets spanning both repos
+    #[serde(skip)]
+    launcher_json_response: serde_json::Value,
+    #[serde(skip)]
+    mods_json_response: serde_json::Value,
+
+    #[serde(skip)]
+    releases_json_response: serde_json::Value,
+
+    // Zip on disk to install instead of downloading, e.g. when GitHub is unreachable
+    #[serde(skip)]
+    local_zip_path: String,
+
+    // Test set of a launcher PR and its companion mods PRs
+    #[serde(skip)]
+    test_set_launcher_pr: Option<i64>,
+    #[serde(skip)]
+    test_set_mods_prs: Vec<i64>,
+    #[serde(skip)]
+    test_set_allow_conflicts: bool,
+
+    // Mods of a PR the user is picking from
+    #[serde(skip)]
+    mod_selection: Option<ModSelection>,
+    // Filled in by the operation listing the mods of a PR once it is done
+    #[serde(skip)]
+    listed_mods: Arc<Mutex<Option<ModSelection>>>,
+
+    // Apply operations running in the background, kept around after finishing to show their result
+    #[serde(skip)]
+    operations: Vec<RunningOperation>,
+
+    // Size budget of the download cache in MiB
+    cache_budget_mib: u64,
+
+    // Budget being edited, only applied once editing is done as shrinking it evicts archives
+    #[serde(skip)]
+    cache_budget_input_mib: u64,
+
+    // Current size of the download cache, `None` if it needs to be recomputed
+    #[serde(skip)]
+    cache_size: Option<u64>,
+
+    // How network requests are retried on transient failures
+    retry_policy: RetryPolicy,
+
+    // Proxy, timeouts and certificates of the HTTP client
+    http_settings: HttpSettings,
+
+    // Why the last edited HTTP settings couldn't be applied
+    #[serde(skip)]
+    http_settings_error: Option<String>,
+
     #[serde(skip)]
     scale_factor: f32,
 }
@@ -35,6 +116,22 @@ impl Default for TemplateApp {
             filter_content: "".to_owned(),
             error_indicator: 0,
             json_response: serde_json::Value::Null,
+            launcher_json_response: serde_json::Value::Null,
+            mods_json_response: serde_json::Value::Null,
+            releases_json_response: serde_json::Value::Null,
+            local_zip_path: "".to_owned(),
+            test_set_launcher_pr: None,
+            test_set_mods_prs: vec![],
+            test_set_allow_conflicts: false,
+            mod_selection: None,
+            listed_mods: Arc::new(Mutex::new(None)),
+            operations: vec![],
+            cache_budget_mib: 2048,
+            cache_budget_input_mib: 2048,
+            cache_size: None,
+            retry_policy: RetryPolicy::default(),
+            http_settings: HttpSettings::default(),
+            http_settings_error: None,
             scale_factor: -1.0,
         }
     }
@@ -46,13 +143,25 @@ impl TemplateApp {
         // This is also where you can customized the look at feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
+        // Clean up after previous runs that didn't get to it themselves
+        staging::sweep_stale_staging_dirs();
+
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        let mut app: Self = match cc.storage {
+            Some(storage) => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+            None => Default::default(),
+        };
+
+        app.cache_budget_input_mib = app.cache_budget_mib;
+
+        http::set_retry_policy(app.retry_policy.clone());
+        if let Err(err) = http::apply_settings(&app.http_settings) {
+            println!("{}, using default HTTP settings", err);
+            app.http_settings_error = Some(format!("{}", err));
         }
 
-        Default::default()
+        app
     }
 }
 
@@ -70,9 +179,60 @@ impl eframe::App for TemplateApp {
             filter_content: filter_content_string,
             error_indicator: error_indicator_value,
             json_response,
+            launcher_json_response,
+            mods_json_response,
+            releases_json_response,
+            local_zip_path,
+            test_set_launcher_pr,
+            test_set_mods_prs,
+            test_set_allow_conflicts,
+            mod_selection,
+            listed_mods,
+            operations,
+            cache_budget_mib,
+            cache_budget_input_mib,
+            cache_size,
+            retry_policy,
+            http_settings,
+            http_settings_error,
             scale_factor,
         } = self;
 
+        for operation in operations.iter_mut() {
+            // Finished operations might have added to the download cache
+            if operation.poll() {
+                *cache_size = None;
+            }
+        }
+        if let Some(listed_mods) = listed_mods.lock().unwrap().take() {
+            *mod_selection = Some(listed_mods);
+        }
+        // Zips dropped onto the window become the local zip to install
+        for dropped_file in &ctx.input().raw.dropped_files {
+            if let Some(path) = &dropped_file.path {
+                let is_zip = path
+                    .extension()
+                    .map_or(false, |extension| extension.eq_ignore_ascii_case("zip"));
+                if is_zip {
+                    println!("Dropped {}", path.display());
+                    *local_zip_path = path.display().to_string();
+                } else {
+                    println!("Ignoring dropped file {} as it isn't a zip", path.display());
+                }
+            }
+        }
+
+        // Operations write into the same game install, so only allow one at a time
+        let operation_running = operations.iter().any(|operation| !operation.is_finished());
+
+        // Only touch the cache while no operation is using it
+        if cache_size.is_none() && !operation_running {
+            if let Err(err) = cache::evict_to_budget(cache_budget_mib.saturating_mul(1024 * 1024)) {
+                println!("{}", err);
+            }
+            *cache_size = Some(cache::total_size());
+        }
+
         if *error_indicator_value != 0 {
             // Stupid way to get the error window to show for a bit
             // This should be replaced with a proper implementation later
@@ -80,6 +240,63 @@ impl eframe::App for TemplateApp {
             *error_indicator_value = 0;
         }
 
+        // Pick which mods of a PR to install, the others come from the base profile
+        let mut close_mod_selection = false;
+        if let Some(selection) = mod_selection {
+            egui::Window::new(format!("Choose mods of PR {}", selection.pr_number))
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(&selection.pr_title);
+                    for (mod_name, picked) in selection.mods.iter_mut() {
+                        ui.checkbox(picked, mod_name.as_str());
+                    }
+                    ui.label("Mods that aren't picked are copied from the R2Northstar profile.");
+                    let picked_mods: Vec<String> = selection
+                        .mods
+                        .iter()
+                        .filter(|(_, picked)| *picked)
+                        .map(|(mod_name, _)| mod_name.clone())
+                        .collect();
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(
+                                !operation_running && !picked_mods.is_empty(),
+                                egui::Button::new("Install picked mods"),
+                            )
+                            .clicked()
+                        {
+                            println!(
+                                "Attempting to install mods {:?} of PR {}",
+                                picked_mods, selection.pr_number
+                            );
+                            let pr_number = selection.pr_number;
+                            let game_install_path = game_install_path.clone();
+                            let json_response = selection.json_response.clone();
+                            operations.push(RunningOperation::spawn(
+                                format!("Apply mods of PR {}: {}", pr_number, selection.pr_title),
+                                ctx,
+                                move |operation| {
+                                    apply_mods_pr_selection(
+                                        pr_number,
+                                        Some(&picked_mods),
+                                        &game_install_path,
+                                        json_response,
+                                        operation,
+                                    )
+                                },
+                            ));
+                            close_mod_selection = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            close_mod_selection = true;
+                        }
+                    });
+                });
+        }
+        if close_mod_selection {
+            *mod_selection = None;
+        }
+
         // Examples of how to create different panels and windows.
         // Pick whichever suits you.
         // Tip: a good default choice is to just keep the `CentralPanel`.
@@ -132,11 +349,12 @@ impl eframe::App for TemplateApp {
                 ui.label(""); // simple spacer
 
                 if ui.button("Refresh NorthstarMods PRs").clicked() {
-                    match util::check_github_api(
+                    match util::check_github_api_once(
                         "https://api.github.com/repos/R2Northstar/NorthstarMods/pulls",
                     ) {
                         Ok(result) => {
                             println!("Successful fetch");
+                            *mods_json_response = result.clone();
                             *json_response = result;
                         }
                         Err(err) => {
@@ -156,11 +374,12 @@ impl eframe::App for TemplateApp {
                 ui.label(""); // simple spacer
 
                 if ui.button("Refresh NorthstarLauncher PRs").clicked() {
-                    match util::check_github_api(
+                    match util::check_github_api_once(
                         "https://api.github.com/repos/R2Northstar/NorthstarLauncher/pulls",
                     ) {
                         Ok(result) => {
                             println!("Successful fetch");
+                            *launcher_json_response = result.clone();
                             *json_response = result;
                         }
                         Err(err) => {
@@ -177,6 +396,243 @@ impl eframe::App for TemplateApp {
                     }
                 }
 
+                ui.label(""); // simple spacer
+
+                if ui.button("Refresh Northstar releases").clicked() {
+                    match util::check_github_api_once(
+                        "https://api.github.com/repos/R2Northstar/Northstar/releases",
+                    ) {
+                        Ok(result) => {
+                            println!("Successful fetch");
+                            *releases_json_response = result;
+                        }
+                        Err(err) => {
+                            println!("{}", err);
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.label(
+                                    egui::RichText::new(format!("Error: {}", err))
+                                        .color(egui::Color32::RED),
+                                );
+                            });
+
+                            *error_indicator_value = 1;
+                        }
+                    }
+                }
+
+                ui.label(""); // simple spacer
+
+                ui.label("Local zip (or drop one onto the window):");
+                ui.text_edit_singleline(local_zip_path);
+                ui.horizontal(|ui| {
+                    let can_install = !operation_running && !local_zip_path.is_empty();
+                    if ui
+                        .add_enabled(can_install, egui::Button::new("Install as launcher"))
+                        .clicked()
+                    {
+                        let zip_path = PathBuf::from(local_zip_path.as_str());
+                        let game_install_path = game_install_path.clone();
+                        operations.push(RunningOperation::spawn(
+                            format!("Install launcher from {}", zip_path.display()),
+                            ctx,
+                            move |operation| {
+                                apply_local_launcher_zip(&zip_path, &game_install_path, operation)
+                            },
+                        ));
+                    }
+                    if ui
+                        .add_enabled(can_install, egui::Button::new("Install as mods"))
+                        .clicked()
+                    {
+                        let zip_path = PathBuf::from(local_zip_path.as_str());
+                        let game_install_path = game_install_path.clone();
+                        operations.push(RunningOperation::spawn(
+                            format!("Install mods from {}", zip_path.display()),
+                            ctx,
+                            move |operation| {
+                                apply_local_mods_zip(&zip_path, &game_install_path, operation)
+                            },
+                        ));
+                    }
+                });
+
+                ui.label(""); // simple spacer
+
+                ui.label(match cache_size {
+                    Some(cache_size) => format!("Download cache: {}", format_bytes(*cache_size)),
+                    None => "Download cache: in use".to_owned(),
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Cache budget:");
+                    let response = ui.add(
+                        egui::DragValue::new(cache_budget_input_mib)
+                            .clamp_range(0..=1024 * 1024)
+                            .suffix(" MiB"),
+                    );
+                    // Values passed while dragging or typing must not evict anything
+                    if (response.drag_released() || response.lost_focus())
+                        && *cache_budget_input_mib != *cache_budget_mib
+                    {
+                        *cache_budget_mib = *cache_budget_input_mib;
+                        *cache_size = None;
+                    }
+                });
+                if ui
+                    .add_enabled(!operation_running, egui::Button::new("Clear cache"))
+                    .clicked()
+                {
+                    if let Err(err) = cache::clear() {
+                        println!("{}", err);
+                    }
+                    *cache_size = None;
+                }
+
+                ui.label(""); // simple spacer
+
+                egui::CollapsingHeader::new("Network settings").show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Network attempts:");
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut retry_policy.max_attempts)
+                                    .clamp_range(1..=10),
+                            )
+                            .changed()
+                        {
+                            http::set_retry_policy(retry_policy.clone());
+                        }
+                    });
+                    ui.label("Proxy URL (empty for none):");
+                    ui.text_edit_singleline(&mut http_settings.proxy_url);
+                    ui.horizontal(|ui| {
+                        ui.label("Connect timeout:");
+                        ui.add(
+                            egui::DragValue::new(&mut http_settings.connect_timeout_secs)
+                                .clamp_range(1..=600)
+                                .suffix(" s"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Read timeout:");
+                        ui.add(
+                            egui::DragValue::new(&mut http_settings.read_timeout_secs)
+                                .clamp_range(1..=600)
+                                .suffix(" s"),
+                        );
+                    });
+                    ui.label("User agent:");
+                    ui.text_edit_singleline(&mut http_settings.user_agent);
+                    ui.label("Extra root certificates (PEM files):");
+                    let mut removed_certificate = None;
+                    for (index, certificate_path) in
+                        http_settings.root_certificate_paths.iter_mut().enumerate()
+                    {
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(certificate_path);
+                            if ui.button("Remove").clicked() {
+                                removed_certificate = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = removed_certificate {
+                        http_settings.root_certificate_paths.remove(index);
+                    }
+                    if ui.button("Add certificate").clicked() {
+                        http_settings.root_certificate_paths.push("".to_owned());
+                    }
+                    if ui.button("Apply network settings").clicked() {
+                        *http_settings_error = match http::apply_settings(http_settings) {
+                            Ok(()) => None,
+                            Err(err) => {
+                                println!("{}", err);
+                                Some(format!("{}", err))
+                            }
+                        };
+                    }
+                    if let Some(err) = http_settings_error {
+                        ui.label(
+                            egui::RichText::new(format!("Error: {}", err))
+                                .color(egui::Color32::RED),
+                        );
+                    }
+                });
+
+                ui.label(""); // simple spacer
+
+                ui.label("Test set:");
+                ui.label(match test_set_launcher_pr {
+                    Some(pr_number) => format!("Launcher PR: {}", pr_number),
+                    None => "Launcher PR: none".to_owned(),
+                });
+                if test_set_mods_prs.is_empty() {
+                    ui.label("Mods PRs: none");
+                } else {
+                    ui.label(format!(
+                        "Mods PRs: {}",
+                        test_set_mods_prs
+                            .iter()
+                            .map(|pr_number| pr_number.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ));
+                }
+                ui.checkbox(
+                    test_set_allow_conflicts,
+                    "Allow conflicting mods PRs (last one wins)",
+                );
+                ui.horizontal(|ui| {
+                    if !test_set_mods_prs.is_empty()
+                        && ui
+                            .add_enabled(!operation_running, egui::Button::new("Apply test set"))
+                            .clicked()
+                    {
+                        let launcher_pr_number = *test_set_launcher_pr;
+                        let mods_pr_numbers = test_set_mods_prs.clone();
+                        let allow_conflicts = *test_set_allow_conflicts;
+                        let game_install_path = game_install_path.clone();
+                        let launcher_json_response = launcher_json_response.clone();
+                        let mods_json_response = mods_json_response.clone();
+                        operations.push(RunningOperation::spawn(
+                            "Apply test set".to_string(),
+                            ctx,
+                            move |operation| {
+                                apply_test_set(
+                                    launcher_pr_number,
+                                    &mods_pr_numbers,
+                                    allow_conflicts,
+                                    &game_install_path,
+                                    launcher_json_response,
+                                    mods_json_response,
+                                    operation,
+                                )
+                            },
+                        ));
+                    }
+                    if ui.button("Clear test set").clicked() {
+                        *test_set_launcher_pr = None;
+                        test_set_mods_prs.clear();
+                    }
+                });
+
+                ui.label(""); // simple spacer
+
+                ui.label("Test profile:");
+                if ui
+                    .add_enabled(
+                        !operation_running,
+                        egui::Button::new("Convert localisation files"),
+                    )
+                    .on_hover_text("Re-encodes localisation files as UTF-16 LE with BOM")
+                    .clicked()
+                {
+                    let game_install_path = game_install_path.clone();
+                    operations.push(RunningOperation::spawn(
+                        "Convert localisation files".to_string(),
+                        ctx,
+                        move |operation| convert_localisation_files(&game_install_path, operation),
+                    ));
+                }
+
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
                     ui.horizontal(|ui| {
                         ui.spacing_mut().item_spacing.x = 0.0;
@@ -192,6 +648,72 @@ impl eframe::App for TemplateApp {
             });
         });
 
+        if !operations.is_empty() {
+            egui::TopBottomPanel::bottom("operations_panel").show(ctx, |ui| {
+                for operation in operations.iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(&operation.name).strong());
+                        if !operation.is_finished()
+                            && ui
+                                .add_enabled(
+                                    !operation.is_cancelling(),
+                                    egui::Button::new("Cancel"),
+                                )
+                                .clicked()
+                        {
+                            operation.cancel();
+                        }
+                    });
+                    match &operation.result {
+                        None => {
+                            match (&operation.download, &operation.extract) {
+                                (Some(download), _) => {
+                                    ui.add(
+                                        egui::ProgressBar::new(download.fraction().unwrap_or(0.0))
+                                            .text(format!(
+                                                "{}: {}",
+                                                operation.status,
+                                                download.description()
+                                            )),
+                                    );
+                                }
+                                (None, Some(extract)) => {
+                                    ui.add(egui::ProgressBar::new(extract.fraction()).text(
+                                        format!("{}: {}", operation.status, extract.description()),
+                                    ));
+                                }
+                                (None, None) => {
+                                    ui.horizontal(|ui| {
+                                        ui.spinner();
+                                        if operation.is_cancelling() {
+                                            ui.label("Cancelling...");
+                                        } else {
+                                            ui.label(&operation.status);
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                        Some(Ok(())) => {
+                            ui.label(egui::RichText::new("Done").color(egui::Color32::GREEN));
+                        }
+                        Some(Err(err)) => {
+                            ui.label(
+                                egui::RichText::new(format!("Error: {}", err))
+                                    .color(egui::Color32::RED),
+                            );
+                        }
+                    }
+                    for line in &operation.report {
+                        ui.label(line);
+                    }
+                }
+                if ui.button("Clear finished").clicked() {
+                    operations.retain(|operation| !operation.is_finished());
+                }
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
 
@@ -210,6 +732,58 @@ impl eframe::App for TemplateApp {
                 "Note that this tool has been deprecated in favour of integrating functionality into FlightCore!",
                 "https://github.com/R2NorthstarTools/FlightCore/blob/ca65fb29fc2895e1912d931b4f486388fabaf7bd/docs/DEV-TOOLS.md#northstar",
             );
+            // Official releases to reset to before applying a PR
+            if let Some(releases_array) = releases_json_response.as_array() {
+                egui::CollapsingHeader::new("Northstar releases").show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_source("releases_scroll_area")
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for elem in releases_array {
+                                let tag_name = elem
+                                    .get("tag_name")
+                                    .and_then(|value| value.as_str())
+                                    .unwrap();
+                                let is_prerelease = elem
+                                    .get("prerelease")
+                                    .and_then(|value| value.as_bool())
+                                    .unwrap_or(false);
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add_enabled(
+                                            !operation_running,
+                                            egui::Button::new("Install release"),
+                                        )
+                                        .clicked()
+                                    {
+                                        println!("Attempting to install release \"{}\"", tag_name);
+                                        let tag_name = tag_name.to_string();
+                                        let game_install_path = game_install_path.clone();
+                                        let releases_json_response = releases_json_response.clone();
+                                        operations.push(RunningOperation::spawn(
+                                            format!("Install release {}", tag_name),
+                                            ctx,
+                                            move |operation| {
+                                                apply_release(
+                                                    &tag_name,
+                                                    &game_install_path,
+                                                    releases_json_response,
+                                                    operation,
+                                                )
+                                            },
+                                        ));
+                                    }
+                                    if is_prerelease {
+                                        ui.label(format!("{} (pre-release)", tag_name));
+                                    } else {
+                                        ui.label(egui::RichText::new(tag_name).strong());
+                                    }
+                                });
+                            }
+                        });
+                });
+            }
+
             // Filter field
             ui.label("Filter:");
             ui.text_edit_singleline(filter_content_string);
@@ -224,6 +798,16 @@ impl eframe::App for TemplateApp {
                             elem.get("number").and_then(|value| value.as_i64()).unwrap();
                         let pr_title = elem.get("title").and_then(|value| value.as_str()).unwrap();
                         let pr_url = elem.get("url").and_then(|value| value.as_str()).unwrap();
+                        // GitHub returns `"repo": null` for PRs whose fork has been deleted
+                        let fork_deleted = elem
+                            .get("head")
+                            .and_then(|value| value.get("repo"))
+                            .map_or(true, |value| value.is_null());
+                        let pr_display_name = if fork_deleted {
+                            format!("{}: {} (fork deleted)", pr_number, pr_title)
+                        } else {
+                            format!("{}: {}", pr_number, pr_title)
+                        };
 
                         // Skip if not in filter
                         if !format!("{}: {}", pr_number, pr_title)
@@ -233,7 +817,10 @@ impl eframe::App for TemplateApp {
                             continue;
                         }
                         ui.horizontal(|ui| {
-                            if ui.button("Apply PR").clicked() {
+                            if ui
+                                .add_enabled(!operation_running, egui::Button::new("Apply PR"))
+                                .clicked()
+                            {
                                 println!("Attempting to install \"{}\"", pr_title);
                                 println!("from: {}", pr_url);
                                 let apply_pr = if pr_url.contains("NorthstarLauncher") {
@@ -241,20 +828,60 @@ impl eframe::App for TemplateApp {
                                 } else {
                                     apply_mods_pr
                                 };
-                                let apply_pr_result =
-                                    apply_pr(pr_number, game_install_path, json_response.clone());
-                                match apply_pr_result {
-                                    Ok(_) => println!("All good?"),
-                                    Err(err) => {
-                                        println!("{}", err);
-                                        egui::Frame::popup(ui.style()).show(ui, |ui| {
-                                            ui.label(
-                                                egui::RichText::new(format!("Error: {}", err))
-                                                    .color(egui::Color32::RED),
-                                            );
+                                let game_install_path = game_install_path.clone();
+                                let json_response = json_response.clone();
+                                operations.push(RunningOperation::spawn(
+                                    format!("Apply PR {}: {}", pr_number, pr_title),
+                                    ctx,
+                                    move |operation| {
+                                        apply_pr(
+                                            pr_number,
+                                            &game_install_path,
+                                            json_response,
+                                            operation,
+                                        )
+                                    },
+                                ));
+                            } else if !pr_url.contains("NorthstarLauncher")
+                                && ui
+                                    .add_enabled(
+                                        !operation_running,
+                                        egui::Button::new("Choose mods"),
+                                    )
+                                    .clicked()
+                            {
+                                println!("Listing mods of \"{}\"", pr_title);
+                                let pr_title = pr_title.to_string();
+                                let game_install_path = game_install_path.clone();
+                                let json_response = json_response.clone();
+                                let listed_mods = listed_mods.clone();
+                                operations.push(RunningOperation::spawn(
+                                    format!("List mods of PR {}: {}", pr_number, pr_title),
+                                    ctx,
+                                    move |operation| {
+                                        let mod_names = list_mods_pr(
+                                            pr_number,
+                                            &game_install_path,
+                                            json_response.clone(),
+                                            operation,
+                                        )?;
+                                        *listed_mods.lock().unwrap() = Some(ModSelection {
+                                            pr_number,
+                                            pr_title,
+                                            json_response,
+                                            mods: mod_names
+                                                .into_iter()
+                                                .map(|mod_name| (mod_name, true))
+                                                .collect(),
                                         });
-                                        *error_indicator_value = 1;
-                                    }
+                                        Ok(())
+                                    },
+                                ));
+                            } else if ui.button("Add to test set").clicked() {
+                                if pr_url.contains("NorthstarLauncher") {
+                                    *test_set_launcher_pr = Some(pr_number);
+                                } else if !test_set_mods_prs.contains(&pr_number) {
+                                    test_set_mods_prs.push(pr_number);
                                 }
                             } else {
                                 // This is a quick and dirty way to colour PR that don't have a testing labels as lighter colour to indicate no need for testing.
@@ -275,12 +902,12 @@ impl eframe::App for TemplateApp {
                                 }
                                 if temp_bool {
                                     ui.label(
-                                        egui::RichText::new(format!("{}: {}", pr_number, pr_title))
+                                        egui::RichText::new(&pr_display_name)
                                             .strong(),
                                     );
                                 } else {
                                     ui.label(
-                                        egui::RichText::new(format!("{}: {}", pr_number, pr_title))
+                                        egui::RichText::new(&pr_display_name)
                                             .color(egui::Color32::GRAY),
                                     );
                                 }
@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::download::{download_zip, sha256_file, verify_zip, DownloadedArchive, ExpectedDownload};
+use super::extract::ArchiveSource;
+use super::operation::OperationContext;
+use super::staging::tool_folder;
+
+/// File in the cache folder listing all cached archives
+const CACHE_INDEX_FILE: &str = "index.json";
+
+/// A single cached archive
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CacheEntry {
+    /// What the archive contains, e.g. `R2Northstar/NorthstarMods@<commit sha>`
+    key: String,
+    file_name: String,
+    size_bytes: u64,
+    /// Lowercase hex encoded SHA-256 digest of the archive
+    #[serde(default)]
+    sha256: String,
+    /// Seconds since UNIX epoch, used for least-recently-used eviction
+    last_used: u64,
+}
+
+/// Folder downloaded archives are cached in
+pub fn cache_folder() -> PathBuf {
+    tool_folder().join("artifacts")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn read_index(folder: &Path) -> Vec<CacheEntry> {
+    fs::read_to_string(folder.join(CACHE_INDEX_FILE))
+        .ok()
+        .and_then(|index| serde_json::from_str(&index).ok())
+        .unwrap_or_default()
+}
+
+fn write_index(folder: &Path, entries: &[CacheEntry]) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(folder)?;
+    fs::write(
+        folder.join(CACHE_INDEX_FILE),
+        serde_json::to_string_pretty(entries)?,
+    )?;
+    Ok(())
+}
+
+/// Copies the cached archive for `key` to `zip_path`, `None` if there is no intact one
+pub fn get(
+    key: &str,
+    zip_path: &Path,
+    expected: &ExpectedDownload,
+    operation: &OperationContext,
+) -> Result<Option<DownloadedArchive>, anyhow::Error> {
+    let folder = cache_folder();
+    let mut entries = read_index(&folder);
+
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.key == key) {
+        if fs::copy(folder.join(&entry.file_name), zip_path).is_ok() {
+            // Make sure the cached copy didn't get damaged on disk
+            let sha256 = sha256_file(zip_path)?;
+            let source = ArchiveSource::File(zip_path.to_path_buf());
+            if sha256 == entry.sha256
+                && expected.verify(entry.size_bytes, &sha256).is_ok()
+                && verify_zip(&source).is_ok()
+            {
+                operation.status(&format!("Using cached download of {}", key));
+                entry.last_used = now();
+                write_index(&folder, &entries)?;
+                return Ok(Some(DownloadedArchive { source, sha256 }));
+            }
+            println!("Cached file for {} is corrupt", key);
+        } else {
+            println!("Cached file for {} is missing", key);
+        }
+    }
+    Ok(None)
+}
+
+/// Gets the archive for `key` from the cache or downloads it from `download_url`
+///
+/// Cached archives are copied to `zip_path`, downloads may be kept in memory instead, see
+/// [`download_zip`]. Either way callers don't need to care whether it was cached.
+pub fn cached_download_zip(
+    key: &str,
+    download_url: String,
+    zip_path: &Path,
+    expected: &ExpectedDownload,
+    operation: &OperationContext,
+) -> Result<DownloadedArchive, anyhow::Error> {
+    if let Some(cached) = get(key, zip_path, expected, operation)? {
+        return Ok(cached);
+    }
+
+    let downloaded = download_zip(download_url, zip_path, expected, operation)?;
+    if let Err(err) = insert(key, &downloaded) {
+        // Not being able to cache shouldn't fail the install
+        println!("Failed caching {}: {}", key, err);
+    }
+    Ok(downloaded)
+}
+
+/// Stores a downloaded archive in the cache under `key`
+pub fn insert(key: &str, downloaded: &DownloadedArchive) -> Result<(), anyhow::Error> {
+    let folder = cache_folder();
+    fs::create_dir_all(&folder)?;
+
+    let file_name = format!("{:x}.zip", Sha256::digest(key.as_bytes()));
+    let size_bytes = match &downloaded.source {
+        ArchiveSource::InMemory(bytes) => {
+            fs::write(folder.join(&file_name), bytes)?;
+            bytes.len() as u64
+        }
+        ArchiveSource::File(path) => fs::copy(path, folder.join(&file_name))?,
+    };
+
+    let mut entries = read_index(&folder);
+    entries.retain(|entry| entry.key != key);
+    entries.push(CacheEntry {
+        key: key.to_string(),
+        file_name,
+        size_bytes,
+        sha256: downloaded.sha256.clone(),
+        last_used: now(),
+    });
+    write_index(&folder, &entries)
+}
+
+/// Total size of all cached archives in bytes
+pub fn total_size() -> u64 {
+    read_index(&cache_folder())
+        .iter()
+        .map(|entry| entry.size_bytes)
+        .sum()
+}
+
+/// Removes least recently used archives until the cache fits into `budget_bytes`
+pub fn evict_to_budget(budget_bytes: u64) -> Result<(), anyhow::Error> {
+    let folder = cache_folder();
+    let mut entries = read_index(&folder);
+    entries.sort_by_key(|entry| entry.last_used);
+
+    let mut total_bytes: u64 = entries.iter().map(|entry| entry.size_bytes).sum();
+    let mut evicted = 0;
+    for entry in &entries {
+        if total_bytes <= budget_bytes {
+            break;
+        }
+        println!("Evicting {} from cache", entry.key);
+        if let Err(err) = fs::remove_file(folder.join(&entry.file_name)) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(anyhow!("Failed evicting {}: {}", entry.key, err));
+            }
+        }
+        total_bytes -= entry.size_bytes;
+        evicted += 1;
+    }
+    if evicted > 0 {
+        write_index(&folder, &entries[evicted..])?;
+    }
+    Ok(())
+}
+
+/// Removes all cached archives
+pub fn clear() -> Result<(), anyhow::Error> {
+    evict_to_budget(0)
+}
@@ -0,0 +1,415 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::extract::ArchiveSource;
+use super::http;
+use super::http::send_with_retry;
+use super::operation::{DownloadProgress, OperationContext};
+use super::staging::tool_folder;
+
+/// What a download is expected to look like, if known before downloading
+#[derive(Debug, Clone, Default)]
+pub struct ExpectedDownload {
+    pub size_bytes: Option<u64>,
+    /// Lowercase hex encoded SHA-256 digest
+    pub sha256: Option<String>,
+}
+
+impl ExpectedDownload {
+    /// Checks a finished download against the expected size and checksum
+    pub fn verify(&self, size_bytes: u64, sha256: &str) -> Result<(), anyhow::Error> {
+        if let Some(expected_size_bytes) = self.size_bytes {
+            if size_bytes != expected_size_bytes {
+                return Err(anyhow!(
+                    "Download is corrupt, expected {} bytes but got {}",
+                    expected_size_bytes,
+                    size_bytes
+                ));
+            }
+        }
+        if let Some(expected_sha256) = &self.sha256 {
+            if !expected_sha256.eq_ignore_ascii_case(sha256) {
+                return Err(anyhow!(
+                    "Download is corrupt, expected SHA-256 {} but got {}",
+                    expected_sha256,
+                    sha256
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Server answered a download request with an unsuccessful status
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: StatusCode,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Couldn't download zip. Received error code \"{}\"",
+            self.status
+        )
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Whether a download failed because the server doesn't have the file
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<HttpStatusError>()
+        .map_or(false, |err| err.status == StatusCode::NOT_FOUND)
+}
+
+/// Computes the SHA-256 digest of a file
+pub fn sha256_file(path: &Path) -> Result<String, anyhow::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Checks that an archive is a readable zip archive
+pub fn verify_zip(archive_source: &ArchiveSource) -> Result<(), anyhow::Error> {
+    match zip::ZipArchive::new(archive_source.reader()?) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(anyhow!("File is not a valid zip archive: {}", err)),
+    }
+}
+
+/// Downloads up to this size are kept in memory instead of being written to a staging file
+const IN_MEMORY_DOWNLOAD_LIMIT_BYTES: u64 = 256 * 1024 * 1024;
+
+/// A finished and verified download
+#[derive(Debug, Clone)]
+pub struct DownloadedArchive {
+    pub source: ArchiveSource,
+    /// Lowercase hex encoded SHA-256 digest
+    pub sha256: String,
+}
+
+/// Where the received data of a download goes
+enum DownloadSink {
+    Memory(Vec<u8>),
+    File(File),
+}
+
+impl DownloadSink {
+    /// Appends data, moving everything to `part_path` once it gets too large for memory
+    fn write_all(&mut self, data: &[u8], part_path: &Path) -> io::Result<()> {
+        match self {
+            DownloadSink::Memory(bytes)
+                if (bytes.len() + data.len()) as u64 > IN_MEMORY_DOWNLOAD_LIMIT_BYTES =>
+            {
+                println!("Download is too large for memory, continuing in staging file");
+                let mut file = File::create(part_path)?;
+                file.write_all(bytes)?;
+                file.write_all(data)?;
+                *self = DownloadSink::File(file);
+                Ok(())
+            }
+            DownloadSink::Memory(bytes) => {
+                bytes.extend_from_slice(data);
+                Ok(())
+            }
+            DownloadSink::File(file) => file.write_all(data),
+        }
+    }
+
+    /// Makes sure everything received so far is in `part_path`, so it can be resumed later
+    fn persist(self, part_path: &Path) -> io::Result<()> {
+        match self {
+            DownloadSink::Memory(bytes) => fs::write(part_path, bytes),
+            DownloadSink::File(file) => file.sync_all(),
+        }
+    }
+}
+
+/// Folder partial downloads are kept in until they complete
+///
+/// Lives outside of the per-operation staging directories so a later attempt can resume them.
+fn partial_downloads_folder() -> PathBuf {
+    tool_folder().join("partial-downloads")
+}
+
+/// Metadata stored next to a partial download to decide whether it can be resumed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PartialDownload {
+    url: String,
+    /// Strong validator of the response the partial data came from
+    etag: String,
+}
+
+/// Paths of the partial data and its metadata for the given URL
+fn partial_download_paths(download_url: &str) -> (PathBuf, PathBuf) {
+    let key = format!("{:x}", Sha256::digest(download_url.as_bytes()));
+    let folder = partial_downloads_folder();
+    (
+        folder.join(format!("{}.part", key)),
+        folder.join(format!("{}.json", key)),
+    )
+}
+
+/// Returns how many bytes of a previous attempt can be resumed and the validator to resume them with
+fn resumable_partial_download(
+    download_url: &str,
+    part_path: &Path,
+    meta_path: &Path,
+) -> Option<(u64, String)> {
+    let partial_download: PartialDownload =
+        serde_json::from_str(&fs::read_to_string(meta_path).ok()?).ok()?;
+    if partial_download.url != download_url {
+        return None;
+    }
+    let resumable_bytes = fs::metadata(part_path).ok()?.len();
+    if resumable_bytes == 0 {
+        return None;
+    }
+    Some((resumable_bytes, partial_download.etag))
+}
+
+/// Removes partial data and metadata of a download
+fn discard_partial_download(part_path: &Path, meta_path: &Path) {
+    let _ = fs::remove_file(part_path);
+    let _ = fs::remove_file(meta_path);
+}
+
+/// Checks whether a partial response continues at the given byte offset
+fn content_range_starts_at(resp: &reqwest::blocking::Response, offset: u64) -> bool {
+    resp.headers()
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| {
+            value.starts_with(&format!("bytes {}-", offset))
+        })
+}
+
+/// Downloads a file from given URL, reporting progress to the given operation
+///
+/// Partial downloads are kept around if the connection drops and resumed with a `Range`
+/// request on the next attempt, provided the server sent a strong `ETag` to validate against.
+///
+/// Downloads small enough to fit into memory are kept there, so the archive can be extracted
+/// without writing and reading a temporary file. Larger ones end up at `zip_path`.
+///
+/// Returns the downloaded archive after checking it against `expected`.
+pub fn download_zip(
+    download_url: String,
+    zip_path: &Path,
+    expected: &ExpectedDownload,
+    operation: &OperationContext,
+) -> Result<DownloadedArchive, anyhow::Error> {
+    operation.status("Downloading file");
+    fs::create_dir_all(partial_downloads_folder())?;
+    let (part_path, meta_path) = partial_download_paths(&download_url);
+
+    let resume_from = resumable_partial_download(&download_url, &part_path, &meta_path);
+    if resume_from.is_none() {
+        discard_partial_download(&part_path, &meta_path);
+    }
+
+    let client = http::client();
+    let mut request = client.get(&download_url);
+    if let Some((resumable_bytes, etag)) = &resume_from {
+        println!("Resuming download at {} bytes", resumable_bytes);
+        // `If-Range` makes the server send the full file instead if it changed in the meantime
+        request = request
+            .header(RANGE, format!("bytes={}-", resumable_bytes))
+            .header(IF_RANGE, etag);
+    }
+    let mut resp = match request
+        .build()
+        .map_err(anyhow::Error::from)
+        .and_then(|request| send_with_retry(&client, request, operation))
+    {
+        Ok(result) => result,
+        Err(err) => return Err(anyhow!(format!("{}", err))),
+    };
+
+    // Partial data doesn't match what the server has anymore, start over without it
+    if resume_from.is_some() && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        println!("Server rejected range, restarting download");
+        discard_partial_download(&part_path, &meta_path);
+        return download_zip(download_url, zip_path, expected, operation);
+    }
+
+    // Error out earlier if non-successful response
+    if !resp.status().is_success() {
+        println!("Status: {}", resp.status());
+        return Err(HttpStatusError {
+            status: resp.status(),
+        }
+        .into());
+    }
+
+    // Partial response for a range we didn't ask for, nothing sensible to append it to
+    if resp.status() == StatusCode::PARTIAL_CONTENT
+        && !content_range_starts_at(&resp, resume_from.as_ref().map_or(0, |(bytes, _)| *bytes))
+    {
+        println!("Server sent unexpected range, restarting download");
+        discard_partial_download(&part_path, &meta_path);
+        return download_zip(download_url, zip_path, expected, operation);
+    }
+
+    // Only append if the server actually continues where we left off
+    let resumed_bytes = match resume_from {
+        Some((resumable_bytes, _)) if resp.status() == StatusCode::PARTIAL_CONTENT => {
+            resumable_bytes
+        }
+        Some(_) => {
+            println!("Server sent full file, restarting download");
+            0
+        }
+        None => 0,
+    };
+
+    // Remember where the data came from so a later attempt can resume it
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .map(|etag| etag.to_string());
+    let is_resumable = match &etag {
+        Some(etag) => {
+            let partial_download = PartialDownload {
+                url: download_url.clone(),
+                etag: etag.clone(),
+            };
+            fs::write(&meta_path, serde_json::to_string(&partial_download)?).is_ok()
+        }
+        None => {
+            let _ = fs::remove_file(&meta_path);
+            false
+        }
+    };
+
+    // Stream body in chunks so progress can be reported while downloading
+    let total_bytes = resp
+        .content_length()
+        .map(|content_length| resumed_bytes + content_length);
+
+    // Checksum covers the whole file, so include data from previous attempts
+    let mut hasher = Sha256::new();
+    let mut sink = if resumed_bytes > 0 {
+        io::copy(&mut File::open(&part_path)?, &mut hasher)?;
+        DownloadSink::File(OpenOptions::new().append(true).open(&part_path)?)
+    } else if total_bytes.map_or(true, |total_bytes| {
+        total_bytes <= IN_MEMORY_DOWNLOAD_LIMIT_BYTES
+    }) {
+        DownloadSink::Memory(Vec::with_capacity(total_bytes.unwrap_or(0) as usize))
+    } else {
+        DownloadSink::File(File::create(&part_path)?)
+    };
+
+    let mut received_bytes = resumed_bytes;
+    let mut buffer = vec![0; 64 * 1024];
+    let download_start = Instant::now();
+    let mut last_progress_report = download_start;
+    loop {
+        // Partial data of a cancelled download is not worth keeping around
+        if let Err(err) = operation.check_cancelled() {
+            drop(sink);
+            discard_partial_download(&part_path, &meta_path);
+            return Err(err);
+        }
+
+        let read_bytes = match resp.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read_bytes) => read_bytes,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                if is_resumable && sink.persist(&part_path).is_ok() {
+                    return Err(anyhow!(
+                        "Download interrupted after {} bytes, retry to resume: {}",
+                        received_bytes,
+                        err
+                    ));
+                }
+                discard_partial_download(&part_path, &meta_path);
+                return Err(anyhow!("Download failed: {}", err));
+            }
+        };
+        sink.write_all(&buffer[..read_bytes], &part_path)?;
+        hasher.update(&buffer[..read_bytes]);
+        received_bytes += read_bytes as u64;
+
+        // Limit how often we report to not flood the UI
+        if last_progress_report.elapsed() >= Duration::from_millis(100) {
+            last_progress_report = Instant::now();
+            operation.download_progress(DownloadProgress {
+                received_bytes,
+                total_bytes,
+                bytes_per_second: (received_bytes - resumed_bytes) as f64
+                    / download_start.elapsed().as_secs_f64(),
+            });
+        }
+    }
+
+    // Connection closed early without an error, keep what we have to resume later
+    if let Some(total_bytes) = total_bytes {
+        if received_bytes < total_bytes {
+            if !is_resumable || sink.persist(&part_path).is_err() {
+                discard_partial_download(&part_path, &meta_path);
+            }
+            return Err(anyhow!(
+                "Download ended early after {} of {} bytes",
+                received_bytes,
+                total_bytes
+            ));
+        }
+    }
+
+    let sha256 = format!("{:x}", hasher.finalize());
+    println!(
+        "Downloaded {} bytes with SHA-256 {}",
+        received_bytes, sha256
+    );
+
+    let source = match sink {
+        DownloadSink::Memory(bytes) => {
+            // A partial file from an earlier attempt can be left over if the server didn't resume
+            discard_partial_download(&part_path, &meta_path);
+            ArchiveSource::InMemory(bytes)
+        }
+        DownloadSink::File(file) => {
+            drop(file);
+            ArchiveSource::File(part_path.clone())
+        }
+    };
+
+    // Corrupt data won't get better by resuming, throw it away
+    if let Err(err) = expected
+        .verify(received_bytes, &sha256)
+        .and_then(|_| verify_zip(&source))
+    {
+        discard_partial_download(&part_path, &meta_path);
+        return Err(err);
+    }
+
+    // Move finished download to where the caller expects it
+    let source = match source {
+        ArchiveSource::File(_) => {
+            if fs::rename(&part_path, zip_path).is_err() {
+                fs::copy(&part_path, zip_path)?;
+            }
+            discard_partial_download(&part_path, &meta_path);
+            ArchiveSource::File(zip_path.to_path_buf())
+        }
+        in_memory => in_memory,
+    };
+    println!("Download done");
+    Ok(DownloadedArchive { source, sha256 })
+}
@@ -0,0 +1,1137 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use zip::result::ZipError;
+
+use super::filter::ExtractFilter;
+use super::operation::{ExtractProgress, OperationContext};
+
+/// Anything an archive can be read from
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Where the bytes of an archive to extract come from
+#[derive(Debug, Clone)]
+pub enum ArchiveSource {
+    /// Downloaded straight into memory, so no temporary zip file is needed
+    InMemory(Vec<u8>),
+    /// Zip file on disk, used for large downloads, cached copies and local zips
+    File(PathBuf),
+}
+
+impl ArchiveSource {
+    /// Opens the archive for reading from the start
+    pub fn reader(&self) -> io::Result<Box<dyn ReadSeek + '_>> {
+        match self {
+            ArchiveSource::InMemory(bytes) => Ok(Box::new(Cursor::new(bytes.as_slice()))),
+            ArchiveSource::File(path) => Ok(Box::new(fs::File::open(path)?)),
+        }
+    }
+}
+
+/// Why extracting an archive failed
+#[derive(Debug)]
+pub enum ExtractError {
+    /// Archive file couldn't be opened
+    Open {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// File isn't a zip archive or its central directory is damaged
+    InvalidArchive(ZipError),
+    /// A single entry couldn't be read
+    InvalidEntry {
+        index: usize,
+        source: ZipError,
+    },
+    /// Tar archive nested inside the zip is damaged
+    InvalidTar(io::Error),
+    /// Archive nested inside the zip couldn't be read
+    NestedArchive {
+        entry_name: String,
+        source: io::Error,
+    },
+    EmptyArchive,
+    /// Entry would end up outside of the destination, e.g. `../file` or `/file`, possibly after renaming
+    UnsafePath {
+        entry_name: String,
+    },
+    /// Archive was expected to have a single top-level folder but this entry isn't inside it
+    MissingTopFolder {
+        entry_name: String,
+    },
+    /// The same file appears more than once in the archive
+    DuplicateEntry {
+        entry_name: String,
+    },
+    /// None of the files in the archive were selected for extraction
+    NothingSelected,
+    /// Writing an extracted file or folder failed
+    Write {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Cancelled,
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::Open { path, source } => {
+                write!(f, "Couldn't open \"{}\": {}", path.display(), source)
+            }
+            ExtractError::InvalidArchive(source) => {
+                write!(f, "File is not a valid zip archive: {}", source)
+            }
+            ExtractError::InvalidEntry { index, source } => {
+                write!(f, "Couldn't read entry {} of archive: {}", index, source)
+            }
+            ExtractError::InvalidTar(source) => {
+                write!(f, "Couldn't read nested tar archive: {}", source)
+            }
+            ExtractError::NestedArchive { entry_name, source } => write!(
+                f,
+                "Couldn't unpack nested archive \"{}\": {}",
+                entry_name, source
+            ),
+            ExtractError::EmptyArchive => write!(f, "Archive is empty"),
+            ExtractError::UnsafePath { entry_name } => write!(
+                f,
+                "Refusing to extract \"{}\" as it would end up outside of the target folder",
+                entry_name
+            ),
+            ExtractError::MissingTopFolder { entry_name } => write!(
+                f,
+                "Expected everything in the archive to be inside a single folder, but \"{}\" isn't",
+                entry_name
+            ),
+            ExtractError::DuplicateEntry { entry_name } => {
+                write!(f, "Archive contains \"{}\" more than once", entry_name)
+            }
+            ExtractError::NothingSelected => {
+                write!(f, "Archive doesn't contain any of the files to install")
+            }
+            ExtractError::Write { path, source } => {
+                write!(f, "Failed extracting to \"{}\": {}", path.display(), source)
+            }
+            ExtractError::Cancelled => write!(f, "Operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExtractError::Open { source, .. }
+            | ExtractError::Write { source, .. }
+            | ExtractError::NestedArchive { source, .. }
+            | ExtractError::InvalidTar(source) => Some(source),
+            ExtractError::InvalidArchive(source) | ExtractError::InvalidEntry { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn write_error(path: &Path) -> impl FnOnce(io::Error) -> ExtractError + '_ {
+    move |source| ExtractError::Write {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Turns the name of an archive entry into a path relative to the extraction destination
+///
+/// Returns `None` for names that could point outside of the destination on any platform, i.e.
+/// absolute paths, drive letters and `..`. Both `/` and `\` are treated as separators.
+fn entry_relative_path(entry_name: &str) -> Option<PathBuf> {
+    if entry_name.contains('\0') || entry_name.starts_with('/') || entry_name.starts_with('\\') {
+        return None;
+    }
+    let mut relative_path = PathBuf::new();
+    for part in entry_name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            // Drive letters and alternate data streams on Windows
+            part if part.contains(':') => return None,
+            part => relative_path.push(part),
+        }
+    }
+    Some(relative_path)
+}
+
+/// Nested archives larger than this are unpacked through a temporary file instead of memory
+const NESTED_ARCHIVE_MEMORY_LIMIT_BYTES: usize = 256 * 1024 * 1024;
+
+/// How many archives inside archives are unpacked at most
+const MAX_NESTING_DEPTH: usize = 3;
+
+/// Formats of archives that can be nested inside a zip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NestedFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn nested_format(entry_name: &str) -> Option<NestedFormat> {
+    let entry_name = entry_name.to_lowercase();
+    if entry_name.ends_with(".zip") {
+        Some(NestedFormat::Zip)
+    } else if entry_name.ends_with(".tar.gz") || entry_name.ends_with(".tgz") {
+        Some(NestedFormat::TarGz)
+    } else if entry_name.ends_with(".tar") {
+        Some(NestedFormat::Tar)
+    } else {
+        None
+    }
+}
+
+/// Where the files of an archive start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveRoot {
+    /// Everything is inside this single top-level folder, e.g. `NorthstarMods-1234abc`
+    Folder(PathBuf),
+    /// Files start at the root of the archive, e.g. this entry isn't inside a common folder
+    Flat { entry_name: String },
+}
+
+impl fmt::Display for ArchiveRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveRoot::Folder(top_folder) => write!(f, "folder \"{}\"", top_folder.display()),
+            ArchiveRoot::Flat { .. } => write!(f, "archive root"),
+        }
+    }
+}
+
+/// Finds the folder all entries of an archive are inside of, given their names and whether
+/// they are folders
+///
+/// Doesn't rely on the order of entries or on folders having entries of their own.
+fn archive_root(entries: &[(String, bool)]) -> Result<ArchiveRoot, ExtractError> {
+    let mut top_folder: Option<PathBuf> = None;
+    for (entry_name, is_dir) in entries {
+        let relative_path = match entry_relative_path(entry_name) {
+            Some(path) => path,
+            None => {
+                return Err(ExtractError::UnsafePath {
+                    entry_name: entry_name.clone(),
+                })
+            }
+        };
+        let mut components = relative_path.components();
+        let first_component = match components.next() {
+            Some(component) => PathBuf::from(component.as_os_str()),
+            None => continue,
+        };
+        // Files directly at the root can't be inside a common folder
+        let is_root_file = !is_dir && components.next().is_none();
+        match &top_folder {
+            _ if is_root_file => {}
+            None => {
+                top_folder = Some(first_component);
+                continue;
+            }
+            Some(top_folder) if *top_folder == first_component => continue,
+            Some(_) => {}
+        }
+        return Ok(ArchiveRoot::Flat {
+            entry_name: entry_name.clone(),
+        });
+    }
+    match top_folder {
+        Some(top_folder) => Ok(ArchiveRoot::Folder(top_folder)),
+        // No entries or only ones that are the root itself
+        None => Err(ExtractError::EmptyArchive),
+    }
+}
+
+/// State of an extraction that is shared by all archive formats
+struct Extractor<'a> {
+    destination: &'a Path,
+    filter: &'a ExtractFilter,
+    operation: &'a OperationContext,
+    /// Root of the archive whose entries are being extracted, resolved before the first entry
+    root: Option<ArchiveRoot>,
+    extracted_paths: HashSet<PathBuf>,
+    last_progress_report: Option<Instant>,
+}
+
+impl Extractor<'_> {
+    fn check_cancelled(&self) -> Result<(), ExtractError> {
+        if self.operation.is_cancelled() {
+            return Err(ExtractError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Resolves the root of the archive about to be extracted from the names of all its entries
+    fn resolve_root(&mut self, entries: &[(String, bool)]) -> Result<(), ExtractError> {
+        let root = archive_root(entries)?;
+        if let ArchiveRoot::Flat { entry_name } = &root {
+            if self.filter.strip_top_folder {
+                return Err(ExtractError::MissingTopFolder {
+                    entry_name: entry_name.clone(),
+                });
+            }
+        }
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// Extracts a single entry of an archive if it passes the filter
+    fn extract_entry(
+        &mut self,
+        index: usize,
+        total_entries: usize,
+        entry_name: String,
+        is_dir: bool,
+        unix_mode: Option<u32>,
+        contents: &mut dyn Read,
+    ) -> Result<(), ExtractError> {
+        // Limit how often we report to not flood the UI
+        if self
+            .last_progress_report
+            .map_or(true, |last| last.elapsed() >= Duration::from_millis(100))
+        {
+            self.last_progress_report = Some(Instant::now());
+            self.operation.extract_progress(ExtractProgress {
+                processed_entries: index,
+                total_entries,
+                entry_name: entry_name.clone(),
+            });
+        }
+
+        let mut relative_path = match entry_relative_path(&entry_name) {
+            Some(path) => path,
+            None => return Err(ExtractError::UnsafePath { entry_name }),
+        };
+
+        if self.filter.strip_top_folder {
+            // `resolve_root` made sure everything is inside the top-level folder
+            if let Some(ArchiveRoot::Folder(top_folder)) = &self.root {
+                relative_path = relative_path
+                    .strip_prefix(top_folder)
+                    .unwrap_or(&relative_path)
+                    .to_path_buf();
+            }
+        }
+
+        // Folders are created along with the files in them
+        if is_dir || relative_path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        let target_path = match self.filter.target_path(&relative_path) {
+            Some(target_path) => target_path,
+            None => return Ok(()),
+        };
+        // Renames come from code, but make sure they can't point outside either
+        if !target_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(ExtractError::UnsafePath { entry_name });
+        }
+
+        if !self.extracted_paths.insert(target_path.clone()) {
+            return Err(ExtractError::DuplicateEntry { entry_name });
+        }
+
+        let outpath = self.destination.join(&target_path);
+        if let Some(parent) = outpath.parent() {
+            fs::create_dir_all(parent).map_err(write_error(parent))?;
+        }
+        let mut outfile = fs::File::create(&outpath).map_err(write_error(&outpath))?;
+        io::copy(contents, &mut outfile).map_err(write_error(&outpath))?;
+
+        // Get and Set permissions
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            if let Some(mode) = unix_mode {
+                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))
+                    .map_err(write_error(&outpath))?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = unix_mode;
+
+        Ok(())
+    }
+}
+
+/// Index of the only file in a zip if that file is another archive
+///
+/// GitHub Actions wraps every uploaded artifact in a zip, so uploaded bundles end up nested.
+fn single_nested_archive<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Result<Option<(usize, NestedFormat)>, ExtractError> {
+    let mut files = vec![];
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|source| ExtractError::InvalidEntry { index, source })?;
+        if !entry.is_dir() {
+            files.push((index, entry.name().to_string()));
+        }
+    }
+    match files.as_slice() {
+        [(index, entry_name)] => Ok(nested_format(entry_name).map(|format| (*index, format))),
+        _ => Ok(None),
+    }
+}
+
+fn extract_zip_archive<R: Read + Seek>(
+    mut archive: zip::ZipArchive<R>,
+    extractor: &mut Extractor<'_>,
+    depth: usize,
+) -> Result<(), ExtractError> {
+    if archive.is_empty() {
+        return Err(ExtractError::EmptyArchive);
+    }
+
+    if depth < MAX_NESTING_DEPTH {
+        if let Some((index, format)) = single_nested_archive(&mut archive)? {
+            let mut entry = archive
+                .by_index(index)
+                .map_err(|source| ExtractError::InvalidEntry { index, source })?;
+            let entry_name = entry.name().to_string();
+            println!("Unpacking nested archive \"{}\"", entry_name);
+
+            // Archive readers need to seek, which compressed zip entries can't
+            let mut payload = tempfile::spooled_tempfile(NESTED_ARCHIVE_MEMORY_LIMIT_BYTES);
+            io::copy(&mut entry, &mut payload)
+                .and_then(|_| payload.seek(SeekFrom::Start(0)))
+                .map_err(|source| ExtractError::NestedArchive { entry_name, source })?;
+
+            return match format {
+                NestedFormat::Zip => extract_zip_archive(
+                    zip::ZipArchive::new(payload).map_err(ExtractError::InvalidArchive)?,
+                    extractor,
+                    depth + 1,
+                ),
+                NestedFormat::Tar => extract_tar_archive(payload, false, extractor),
+                NestedFormat::TarGz => extract_tar_archive(payload, true, extractor),
+            };
+        }
+    }
+
+    let total_entries = archive.len();
+    let mut entries = vec![];
+    for index in 0..total_entries {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|source| ExtractError::InvalidEntry { index, source })?;
+        entries.push((entry.name().to_string(), entry.is_dir()));
+    }
+    extractor.resolve_root(&entries)?;
+
+    for index in 0..total_entries {
+        extractor.check_cancelled()?;
+
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|source| ExtractError::InvalidEntry { index, source })?;
+        let entry_name = entry.name().to_string();
+        let is_dir = entry.is_dir();
+        let unix_mode = entry.unix_mode();
+        extractor.extract_entry(
+            index,
+            total_entries,
+            entry_name,
+            is_dir,
+            unix_mode,
+            &mut entry,
+        )?;
+    }
+    Ok(())
+}
+
+/// Reads a tar archive from the start of `payload`, decompressing it on the fly if `gzipped`
+fn open_tar<R: Read + Seek>(
+    payload: &mut R,
+    gzipped: bool,
+) -> Result<tar::Archive<Box<dyn Read + '_>>, ExtractError> {
+    payload
+        .seek(SeekFrom::Start(0))
+        .map_err(ExtractError::InvalidTar)?;
+    let reader: Box<dyn Read> = if gzipped {
+        Box::new(flate2::read::GzDecoder::new(payload))
+    } else {
+        Box::new(payload)
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn extract_tar_archive<R: Read + Seek>(
+    mut payload: R,
+    gzipped: bool,
+    extractor: &mut Extractor<'_>,
+) -> Result<(), ExtractError> {
+    // Tar has no index, so entry names take a separate pass over the whole archive
+    let mut total_entries = 0;
+    let mut entries = vec![];
+    for entry in open_tar(&mut payload, gzipped)?
+        .entries()
+        .map_err(ExtractError::InvalidTar)?
+    {
+        let entry = entry.map_err(ExtractError::InvalidTar)?;
+        let entry_type = entry.header().entry_type();
+        total_entries += 1;
+        if entry_type.is_file() || entry_type.is_dir() {
+            let entry_name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+            entries.push((entry_name, entry_type.is_dir()));
+        }
+    }
+    extractor.resolve_root(&entries)?;
+
+    let mut archive = open_tar(&mut payload, gzipped)?;
+    let entries = archive.entries().map_err(ExtractError::InvalidTar)?;
+    for (index, entry) in entries.enumerate() {
+        extractor.check_cancelled()?;
+
+        let mut entry = entry.map_err(ExtractError::InvalidTar)?;
+        let entry_name = String::from_utf8_lossy(&entry.path_bytes()).to_string();
+        let entry_type = entry.header().entry_type();
+        // Links could point anywhere, so only regular files and folders are extracted
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            println!(
+                "Skipping tar entry \"{}\" of type {:?}",
+                entry_name, entry_type
+            );
+            continue;
+        }
+        let unix_mode = entry.header().mode().ok();
+        extractor.extract_entry(
+            index,
+            total_entries,
+            entry_name,
+            entry_type.is_dir(),
+            unix_mode,
+            &mut entry,
+        )?;
+    }
+    Ok(())
+}
+
+/// What was extracted from an archive
+#[derive(Debug, Clone)]
+pub struct ExtractedArchive {
+    pub extracted_files: usize,
+    /// Root of the archive the files were extracted from, the nested one if unpacked
+    pub root: ArchiveRoot,
+}
+
+/// Extracts the files of a zip archive that pass `filter` into `destination`
+///
+/// If the zip only contains another zip, tar or tar.gz archive, that one is unpacked and
+/// filtered instead. Folders are created as needed for the extracted files, so folders that
+/// end up empty after filtering don't show up.
+///
+/// Nothing outside of `destination` is ever written to, archives with entries that would
+/// escape it are rejected.
+pub fn extract_zip(
+    archive_source: &ArchiveSource,
+    destination: &Path,
+    filter: &ExtractFilter,
+    operation: &OperationContext,
+) -> Result<ExtractedArchive, ExtractError> {
+    let extract_start = Instant::now();
+    let reader = archive_source
+        .reader()
+        .map_err(|source| ExtractError::Open {
+            path: match archive_source {
+                ArchiveSource::File(path) => path.clone(),
+                ArchiveSource::InMemory(_) => PathBuf::new(),
+            },
+            source,
+        })?;
+    let archive = zip::ZipArchive::new(reader).map_err(ExtractError::InvalidArchive)?;
+
+    let mut extractor = Extractor {
+        destination,
+        filter,
+        operation,
+        root: None,
+        extracted_paths: HashSet::new(),
+        last_progress_report: None,
+    };
+    extract_zip_archive(archive, &mut extractor, 0)?;
+
+    let extracted_files = extractor.extracted_paths.len();
+    if extracted_files == 0 {
+        return Err(ExtractError::NothingSelected);
+    }
+
+    operation.report(&format!(
+        "Extracted {} files from {} in {} ms",
+        extracted_files,
+        match archive_source {
+            ArchiveSource::InMemory(_) => "memory",
+            ArchiveSource::File(_) => "file",
+        },
+        extract_start.elapsed().as_millis()
+    ));
+    Ok(ExtractedArchive {
+        extracted_files,
+        root: extractor
+            .root
+            .expect("Root is resolved before extracting any entry"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::FileOptions;
+
+    use super::*;
+
+    /// Writes a zip with the given entries into `dir`, names ending in `/` become directories
+    fn write_zip(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        let zip_path = dir.join("fixture.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        for (name, contents) in entries {
+            if name.ends_with('/') {
+                writer.add_directory(*name, FileOptions::default()).unwrap();
+            } else {
+                writer.start_file(*name, FileOptions::default()).unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        zip_path
+    }
+
+    /// Builds a tar archive with the given files in memory
+    fn tar_bytes(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Writes a zip into `dir` that contains a single file with the given binary contents
+    fn write_wrapping_zip(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+        let zip_path = dir.join("artifact.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(contents).unwrap();
+        writer.finish().unwrap();
+        zip_path
+    }
+
+    fn extract_all(
+        zip_path: &Path,
+        destination: &Path,
+        filter: &ExtractFilter,
+    ) -> Result<usize, ExtractError> {
+        extract_zip(
+            &ArchiveSource::File(zip_path.to_path_buf()),
+            destination,
+            filter,
+            &OperationContext::detached(),
+        )
+        .map(|extracted| extracted.extracted_files)
+    }
+
+    #[test]
+    fn extracts_nested_folders_without_top_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("NorthstarMods-abc/", ""),
+                ("NorthstarMods-abc/Northstar.Client/", ""),
+                ("NorthstarMods-abc/Northstar.Client/mod/", ""),
+                ("NorthstarMods-abc/Northstar.Client/mod.json", "{}"),
+                ("NorthstarMods-abc/Northstar.Client/mod/a.nut", "nested"),
+            ],
+        );
+        let destination = dir.path().join("out");
+
+        let extracted_files = extract_all(
+            &zip_path,
+            &destination,
+            &ExtractFilter::everything().strip_top_folder(),
+        )
+        .unwrap();
+
+        assert_eq!(extracted_files, 2);
+        assert_eq!(
+            fs::read_to_string(destination.join("Northstar.Client/mod/a.nut")).unwrap(),
+            "nested"
+        );
+        assert!(destination.join("Northstar.Client/mod.json").is_file());
+        assert!(!destination.join("NorthstarMods-abc").exists());
+    }
+
+    #[test]
+    fn rejects_empty_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(dir.path(), &[]);
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything(),
+        );
+
+        assert!(matches!(result, Err(ExtractError::EmptyArchive)));
+    }
+
+    #[test]
+    fn rejects_archive_without_top_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[("README.md", "readme"), ("Northstar.Client/mod.json", "{}")],
+        );
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything().strip_top_folder(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::MissingTopFolder { entry_name }) if entry_name == "README.md"
+        ));
+    }
+
+    #[test]
+    fn rejects_entries_outside_top_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("top/", ""),
+                ("top/mod.json", "{}"),
+                ("other/mod.json", "{}"),
+            ],
+        );
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything().strip_top_folder(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::MissingTopFolder { entry_name }) if entry_name == "other/mod.json"
+        ));
+    }
+
+    #[test]
+    fn rejects_duplicate_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("top/", ""),
+                ("top/mod.json", "first"),
+                ("top/mod.json", "second"),
+            ],
+        );
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything().strip_top_folder(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(ExtractError::DuplicateEntry { entry_name }) if entry_name == "top/mod.json"
+        ));
+    }
+
+    #[test]
+    fn rejects_file_that_is_not_a_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("fixture.zip");
+        fs::write(&zip_path, "not a zip").unwrap();
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything(),
+        );
+
+        assert!(matches!(result, Err(ExtractError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn extracts_only_selected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("NorthstarLauncher.exe", "exe"),
+                ("Northstar.dll", "dll"),
+                ("Northstar.pdb", "pdb"),
+            ],
+        );
+        let destination = dir.path().join("out");
+
+        extract_all(
+            &zip_path,
+            &destination,
+            &ExtractFilter::everything()
+                .include("NorthstarLauncher.exe")
+                .include("*.dll"),
+        )
+        .unwrap();
+
+        assert!(destination.join("NorthstarLauncher.exe").is_file());
+        assert!(destination.join("Northstar.dll").is_file());
+        assert!(!destination.join("Northstar.pdb").exists());
+    }
+
+    #[test]
+    fn rejects_archive_without_selected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(dir.path(), &[("Northstar.pdb", "pdb")]);
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything().include("*.dll"),
+        );
+
+        assert!(matches!(result, Err(ExtractError::NothingSelected)));
+    }
+
+    #[test]
+    fn rejects_entries_escaping_destination() {
+        for entry_name in ["../evil.txt", "top/../../evil.txt", "top\\..\\..\\evil.txt"] {
+            let dir = tempfile::tempdir().unwrap();
+            let zip_path = write_zip(dir.path(), &[("top/", ""), (entry_name, "evil")]);
+            let destination = dir.path().join("out").join("nested");
+
+            let result = extract_all(&zip_path, &destination, &ExtractFilter::everything());
+
+            assert!(
+                matches!(&result, Err(ExtractError::UnsafePath { entry_name: name }) if name == entry_name),
+                "{} was not rejected",
+                entry_name
+            );
+            assert!(!dir.path().join("evil.txt").exists());
+            assert!(!dir.path().join("out").join("evil.txt").exists());
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside_path = dir.path().join("evil.txt");
+        let absolute_entry_name = outside_path.to_str().unwrap().replace('\\', "/");
+        for entry_name in [
+            absolute_entry_name.as_str(),
+            "/evil.txt",
+            "\\evil.txt",
+            "C:\\evil.txt",
+            "C:/evil.txt",
+        ] {
+            let zip_path = write_zip(dir.path(), &[(entry_name, "evil")]);
+
+            let result = extract_all(
+                &zip_path,
+                &dir.path().join("out"),
+                &ExtractFilter::everything(),
+            );
+
+            assert!(
+                matches!(&result, Err(ExtractError::UnsafePath { entry_name: name }) if name == entry_name),
+                "{} was not rejected",
+                entry_name
+            );
+            assert!(!outside_path.exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sets_permissions_on_extracted_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("fixture.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        writer
+            .start_file(
+                "NorthstarLauncher.exe",
+                FileOptions::default().unix_permissions(0o755),
+            )
+            .unwrap();
+        writer.write_all(b"exe").unwrap();
+        writer.finish().unwrap();
+        let destination = dir.path().join("out");
+
+        extract_all(&zip_path, &destination, &ExtractFilter::everything()).unwrap();
+
+        let mode = fs::metadata(destination.join("NorthstarLauncher.exe"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[test]
+    fn applies_filter_and_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("top/", ""),
+                ("top/.github/workflows/ci.yml", "ci"),
+                ("top/README.md", "readme"),
+                ("top/Northstar.Client/mod.json", "client"),
+                ("top/Northstar.Custom/mod.json", "custom"),
+            ],
+        );
+        let destination = dir.path().join("out");
+
+        let extracted_files = extract_all(
+            &zip_path,
+            &destination,
+            &ExtractFilter {
+                renames: vec![(
+                    "Northstar.Custom".to_string(),
+                    "Northstar.Renamed".to_string(),
+                )],
+                ..ExtractFilter::everything()
+                    .strip_top_folder()
+                    .exclude(".github/")
+                    .exclude("*.md")
+            },
+        )
+        .unwrap();
+
+        assert_eq!(extracted_files, 2);
+        assert!(destination.join("Northstar.Client/mod.json").is_file());
+        assert_eq!(
+            fs::read_to_string(destination.join("Northstar.Renamed/mod.json")).unwrap(),
+            "custom"
+        );
+        assert!(!destination.join("Northstar.Custom").exists());
+        assert!(!destination.join(".github").exists());
+        assert!(!destination.join("README.md").exists());
+    }
+
+    #[test]
+    fn rejects_renames_escaping_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(dir.path(), &[("Northstar.dll", "dll")]);
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter {
+                renames: vec![("Northstar.dll".to_string(), "../Northstar.dll".to_string())],
+                ..ExtractFilter::everything()
+            },
+        );
+
+        assert!(matches!(result, Err(ExtractError::UnsafePath { .. })));
+        assert!(!dir.path().join("Northstar.dll").exists());
+    }
+
+    #[test]
+    fn extracts_from_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(dir.path(), &[("top/", ""), ("top/mod.json", "{}")]);
+        let destination = dir.path().join("out");
+
+        let extracted = extract_zip(
+            &ArchiveSource::InMemory(fs::read(&zip_path).unwrap()),
+            &destination,
+            &ExtractFilter::everything().strip_top_folder(),
+            &OperationContext::detached(),
+        )
+        .unwrap();
+
+        assert_eq!(extracted.extracted_files, 1);
+        assert_eq!(
+            fs::read_to_string(destination.join("mod.json")).unwrap(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn unpacks_nested_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let inner_zip_path = write_zip(
+            dir.path(),
+            &[("NorthstarLauncher.exe", "exe"), ("Northstar.dll", "dll")],
+        );
+        let zip_path =
+            write_wrapping_zip(dir.path(), "bundle.zip", &fs::read(inner_zip_path).unwrap());
+        let destination = dir.path().join("out");
+
+        let extracted_files = extract_all(
+            &zip_path,
+            &destination,
+            &ExtractFilter::everything().include("NorthstarLauncher.exe"),
+        )
+        .unwrap();
+
+        assert_eq!(extracted_files, 1);
+        assert!(destination.join("NorthstarLauncher.exe").is_file());
+        assert!(!destination.join("bundle.zip").exists());
+    }
+
+    #[test]
+    fn unpacks_nested_tar_gz() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(&tar_bytes(&[
+                ("Northstar/NorthstarLauncher.exe", "exe"),
+                ("Northstar/Northstar.dll", "dll"),
+            ]))
+            .unwrap();
+        let zip_path = write_wrapping_zip(dir.path(), "bundle.tar.gz", &encoder.finish().unwrap());
+        let destination = dir.path().join("out");
+
+        let extracted_files = extract_all(
+            &zip_path,
+            &destination,
+            &ExtractFilter::everything().include("Northstar/*.dll"),
+        )
+        .unwrap();
+
+        assert_eq!(extracted_files, 1);
+        assert_eq!(
+            fs::read_to_string(destination.join("Northstar/Northstar.dll")).unwrap(),
+            "dll"
+        );
+    }
+
+    #[test]
+    fn rejects_unsafe_paths_in_nested_tar() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tar_data = tar_bytes(&[("placeholder.txt", "evil")]);
+        // `tar::Builder` refuses to write `..`, so patch the name in the header directly
+        let name = b"../evil.txt\0";
+        tar_data[..name.len()].copy_from_slice(name);
+        let mut header = tar::Header::from_byte_slice(&tar_data[..512]).clone();
+        header.set_cksum();
+        tar_data[..512].copy_from_slice(header.as_bytes());
+        let zip_path = write_wrapping_zip(dir.path(), "bundle.tar", &tar_data);
+
+        let result = extract_all(
+            &zip_path,
+            &dir.path().join("out"),
+            &ExtractFilter::everything(),
+        );
+
+        assert!(matches!(result, Err(ExtractError::UnsafePath { .. })));
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn keeps_archives_next_to_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[("bundle.zip", "not unpacked"), ("README.md", "readme")],
+        );
+        let destination = dir.path().join("out");
+
+        let extracted_files =
+            extract_all(&zip_path, &destination, &ExtractFilter::everything()).unwrap();
+
+        assert_eq!(extracted_files, 2);
+        assert_eq!(
+            fs::read_to_string(destination.join("bundle.zip")).unwrap(),
+            "not unpacked"
+        );
+    }
+
+    #[test]
+    fn finds_root_regardless_of_entry_order() {
+        let entries = |names: &[&str]| -> Vec<(String, bool)> {
+            names
+                .iter()
+                .map(|name| (name.to_string(), name.ends_with('/')))
+                .collect()
+        };
+
+        assert_eq!(
+            archive_root(&entries(&[
+                "NorthstarMods-abc/Northstar.Client/mod.json",
+                "NorthstarMods-abc/",
+                "NorthstarMods-abc/README.md",
+            ]))
+            .unwrap(),
+            ArchiveRoot::Folder(PathBuf::from("NorthstarMods-abc"))
+        );
+        assert_eq!(
+            archive_root(&entries(&["top/a.txt", "top/b/c.txt"])).unwrap(),
+            ArchiveRoot::Folder(PathBuf::from("top"))
+        );
+        assert_eq!(
+            archive_root(&entries(&["top/a.txt", "README.md"])).unwrap(),
+            ArchiveRoot::Flat {
+                entry_name: "README.md".to_string()
+            }
+        );
+        assert_eq!(
+            archive_root(&entries(&["top/a.txt", "other/"])).unwrap(),
+            ArchiveRoot::Flat {
+                entry_name: "other/".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn strips_top_folder_without_folder_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = write_zip(
+            dir.path(),
+            &[
+                ("NorthstarMods-abc/Northstar.Client/mod.json", "{}"),
+                ("NorthstarMods-abc/Northstar.Custom/mod.json", "{}"),
+            ],
+        );
+        let destination = dir.path().join("out");
+
+        let extracted = extract_zip(
+            &ArchiveSource::File(zip_path),
+            &destination,
+            &ExtractFilter::everything().strip_top_folder(),
+            &OperationContext::detached(),
+        )
+        .unwrap();
+
+        assert_eq!(extracted.extracted_files, 2);
+        assert_eq!(
+            extracted.root,
+            ArchiveRoot::Folder(PathBuf::from("NorthstarMods-abc"))
+        );
+        assert!(destination.join("Northstar.Client/mod.json").is_file());
+        assert!(destination.join("Northstar.Custom/mod.json").is_file());
+    }
+}
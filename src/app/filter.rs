@@ -0,0 +1,180 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Matches a single path segment against a pattern segment supporting `*` and `?`
+fn segment_matches(pattern: &[char], segment: &[char]) -> bool {
+    match (pattern.first(), segment.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            // Either the star matches nothing or it swallows one more character
+            segment_matches(&pattern[1..], segment)
+                || (!segment.is_empty() && segment_matches(pattern, &segment[1..]))
+        }
+        (Some('?'), Some(_)) => segment_matches(&pattern[1..], &segment[1..]),
+        (Some(p), Some(s)) if p == s => segment_matches(&pattern[1..], &segment[1..]),
+        _ => false,
+    }
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        // `**` can stand for any number of segments, including none
+        Some(&"**") => (0..=path.len()).any(|skip| segments_match(&pattern[1..], &path[skip..])),
+        Some(segment_pattern) => {
+            !path.is_empty()
+                && segment_matches(
+                    &segment_pattern.chars().collect::<Vec<char>>(),
+                    &path[0].chars().collect::<Vec<char>>(),
+                )
+                && segments_match(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+/// Checks whether a `/` separated relative path matches a glob pattern
+///
+/// `*` and `?` match within a single path segment, `**` matches any number of segments.
+/// A pattern ending in `/` matches everything inside that folder.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern = match pattern.strip_suffix('/') {
+        Some(folder) => format!("{}/**", folder),
+        None => pattern.to_string(),
+    };
+    let pattern_segments: Vec<&str> = pattern.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern_segments, &path_segments)
+}
+
+/// Joins the segments of a relative path with `/`, independent of the platform
+fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(segment.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Decides which files of an archive are extracted and where to
+#[derive(Debug, Clone, Default)]
+pub struct ExtractFilter {
+    /// Extract the contents of the single top-level folder instead of the folder itself
+    pub strip_top_folder: bool,
+    /// Files have to match at least one of these, unless there are none
+    pub include: Vec<String>,
+    /// Files matching any of these are skipped, even if included
+    pub exclude: Vec<String>,
+    /// Moves files or folders at the first path to the second, both relative to the archive
+    /// root, the first matching prefix wins
+    pub renames: Vec<(String, String)>,
+}
+
+impl ExtractFilter {
+    /// Extracts everything as it is
+    pub fn everything() -> Self {
+        Self::default()
+    }
+
+    pub fn strip_top_folder(mut self) -> Self {
+        self.strip_top_folder = true;
+        self
+    }
+
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Whether a file at `path` relative to the archive root passes include and exclude patterns
+    pub fn selects(&self, path: &Path) -> bool {
+        let path = to_slash_path(path);
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| glob_matches(pattern, &path));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|pattern| glob_matches(pattern, &path))
+    }
+
+    /// Where a file at `path` relative to the archive root ends up, `None` if it is skipped
+    pub fn target_path(&self, path: &Path) -> Option<PathBuf> {
+        if !self.selects(path) {
+            return None;
+        }
+        for (from, to) in &self.renames {
+            if let Ok(rest) = path.strip_prefix(from) {
+                return Some(Path::new(to).join(rest));
+            }
+        }
+        Some(path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_matches("README.md", "README.md"));
+        assert!(!glob_matches("README.md", "Northstar.Client/README.md"));
+        assert!(glob_matches("**/README.md", "Northstar.Client/README.md"));
+        assert!(glob_matches("**/README.md", "README.md"));
+        assert!(glob_matches("*.md", "CONTRIBUTING.md"));
+        assert!(!glob_matches("*.md", "docs/CONTRIBUTING.md"));
+        assert!(glob_matches(".github/", ".github/workflows/ci.yml"));
+        assert!(glob_matches(".github/**", ".github/workflows/ci.yml"));
+        assert!(!glob_matches(".github/", "Northstar.Client/.github/ci.yml"));
+        assert!(glob_matches("Northstar.?ll", "Northstar.dll"));
+        assert!(glob_matches(
+            "mods/*/mod.json",
+            "mods/Northstar.Client/mod.json"
+        ));
+        assert!(!glob_matches("mods/*/mod.json", "mods/a/b/mod.json"));
+    }
+
+    #[test]
+    fn applies_include_exclude_and_renames() {
+        let filter = ExtractFilter {
+            renames: vec![(
+                "Northstar.Custom".to_string(),
+                "Northstar.CustomRenamed".to_string(),
+            )],
+            ..ExtractFilter::everything()
+                .include("Northstar.*/**")
+                .include("*.dll")
+                .exclude("**/*.md")
+        };
+
+        assert_eq!(
+            filter.target_path(Path::new("Northstar.Client/mod.json")),
+            Some(PathBuf::from("Northstar.Client/mod.json"))
+        );
+        assert_eq!(
+            filter.target_path(Path::new("Northstar.Custom/mod/a.nut")),
+            Some(PathBuf::from("Northstar.CustomRenamed/mod/a.nut"))
+        );
+        assert_eq!(
+            filter.target_path(Path::new("wsock32.dll")),
+            Some(PathBuf::from("wsock32.dll"))
+        );
+        assert_eq!(
+            filter.target_path(Path::new("Northstar.Client/README.md")),
+            None
+        );
+        assert_eq!(
+            filter.target_path(Path::new(".github/workflows/ci.yml")),
+            None
+        );
+    }
+}
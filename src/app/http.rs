@@ -0,0 +1,220 @@
+use std::fs;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::{Certificate, Method, Proxy, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::operation::OperationContext;
+
+/// Settings of the HTTP client shared by all requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    /// Proxy all requests are sent through, e.g. `http://proxy.example.com:8080`, empty for none
+    pub proxy_url: String,
+    pub connect_timeout_secs: u64,
+    /// How long to wait for the server to send more data before giving up
+    pub read_timeout_secs: u64,
+    /// PEM files of certificates to trust in addition to the system ones, e.g. for TLS interception
+    pub root_certificate_paths: Vec<String>,
+    pub user_agent: String,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            proxy_url: "".to_owned(),
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            root_certificate_paths: vec![],
+            user_agent: "GeckoEidechse/northstar-dev-testing-helper-tool".to_owned(),
+        }
+    }
+}
+
+impl HttpSettings {
+    fn build_client(&self) -> Result<Client, anyhow::Error> {
+        let mut builder = Client::builder()
+            .user_agent(&self.user_agent)
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            // Blocking client applies this to every read, not the whole request
+            .timeout(Duration::from_secs(self.read_timeout_secs));
+
+        let proxy_url = self.proxy_url.trim();
+        if !proxy_url.is_empty() {
+            match Proxy::all(proxy_url) {
+                Ok(proxy) => builder = builder.proxy(proxy),
+                Err(err) => return Err(anyhow!("Invalid proxy URL \"{}\": {}", proxy_url, err)),
+            }
+        }
+
+        for certificate_path in &self.root_certificate_paths {
+            let certificate = fs::read(certificate_path)
+                .map_err(|err| err.to_string())
+                .and_then(|pem| Certificate::from_pem(&pem).map_err(|err| err.to_string()));
+            match certificate {
+                Ok(certificate) => builder = builder.add_root_certificate(certificate),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Failed loading certificate \"{}\": {}",
+                        certificate_path,
+                        err
+                    ))
+                }
+            }
+        }
+
+        match builder.build() {
+            Ok(client) => Ok(client),
+            Err(err) => Err(anyhow!("Failed setting up HTTP client: {}", err)),
+        }
+    }
+}
+
+/// Client used by all requests, rebuilt whenever the settings change
+static CLIENT: Lazy<RwLock<Client>> = Lazy::new(|| {
+    RwLock::new(
+        HttpSettings::default()
+            .build_client()
+            .expect("Default HTTP settings are valid"),
+    )
+});
+
+/// Replaces the shared client with one using the given settings
+///
+/// The previous client stays in use if the settings are invalid.
+pub fn apply_settings(http_settings: &HttpSettings) -> Result<(), anyhow::Error> {
+    let client = http_settings.build_client()?;
+    *CLIENT.write().unwrap() = client;
+    Ok(())
+}
+
+/// Shared HTTP client, cheap to clone
+pub fn client() -> Client {
+    CLIENT.read().unwrap().clone()
+}
+
+/// How often and how patiently requests failing for transient reasons are retried
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the retry following the given failed attempt, starting at 1
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff_ms = self
+            .initial_backoff_ms
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_backoff_ms);
+        // Randomise the second half so clients that failed together don't retry in lockstep
+        Duration::from_millis(backoff_ms / 2 + fastrand::u64(0..=backoff_ms / 2))
+    }
+}
+
+/// Retry policy used by all requests, set from the UI settings
+static RETRY_POLICY: Lazy<RwLock<RetryPolicy>> = Lazy::new(Default::default);
+
+pub fn set_retry_policy(retry_policy: RetryPolicy) {
+    *RETRY_POLICY.write().unwrap() = retry_policy;
+}
+
+/// Only requests that can be sent twice without side effects are safe to retry
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Status codes that indicate the server might succeed if asked again later
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_retryable_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request()
+}
+
+/// Sends a request, retrying timeouts, connection errors and retryable status codes
+///
+/// Returns the last response or error once attempts run out or the failure isn't retryable.
+/// Waiting between attempts stops early if `operation` gets cancelled.
+pub fn send_with_retry(
+    client: &Client,
+    request: Request,
+    operation: &OperationContext,
+) -> Result<Response, anyhow::Error> {
+    let retry_policy = RETRY_POLICY.read().unwrap().clone();
+    let mut request = request;
+    let mut attempt = 1;
+    loop {
+        let url = request.url().clone();
+        // Sending consumes the request, so keep a copy in case it needs to be sent again
+        let retry_request =
+            if attempt < retry_policy.max_attempts && is_idempotent(request.method()) {
+                request.try_clone()
+            } else {
+                None
+            };
+
+        let result = client.execute(request);
+        let reason = match &result {
+            Ok(resp) if is_retryable_status(resp.status()) => format!("status {}", resp.status()),
+            Err(err) if is_retryable_error(err) => format!("{}", err),
+            _ => return Ok(result?),
+        };
+        request = match retry_request {
+            Some(retry_request) => retry_request,
+            None => return Ok(result?),
+        };
+
+        let backoff = retry_policy.backoff(attempt);
+        attempt += 1;
+        println!(
+            "Request to {} failed ({}), retrying in {:.1}s (attempt {} of {})",
+            url,
+            reason,
+            backoff.as_secs_f64(),
+            attempt,
+            retry_policy.max_attempts
+        );
+        // Sleep in steps so a cancelled operation doesn't wait out the whole backoff
+        let backoff_end = Instant::now() + backoff;
+        loop {
+            operation.check_cancelled()?;
+            let now = Instant::now();
+            if now >= backoff_end {
+                break;
+            }
+            std::thread::sleep((backoff_end - now).min(Duration::from_millis(100)));
+        }
+    }
+}
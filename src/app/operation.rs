@@ -0,0 +1,270 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+/// Progress of a running download
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    pub received_bytes: u64,
+    /// Taken from `Content-Length`, `None` if the server didn't send one
+    pub total_bytes: Option<u64>,
+    pub bytes_per_second: f64,
+}
+
+impl DownloadProgress {
+    /// Fraction of the download that is done, if the total size is known
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total_bytes {
+            Some(total_bytes) if total_bytes > 0 => {
+                Some(self.received_bytes as f32 / total_bytes as f32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Estimated time until the download finishes at the current throughput
+    pub fn eta(&self) -> Option<Duration> {
+        let total_bytes = self.total_bytes?;
+        if self.bytes_per_second <= 0.0 {
+            return None;
+        }
+        let remaining_bytes = total_bytes.saturating_sub(self.received_bytes);
+        Some(Duration::from_secs_f64(
+            remaining_bytes as f64 / self.bytes_per_second,
+        ))
+    }
+
+    /// Human readable summary, e.g. `1.2 MiB / 3.4 MiB (500.0 KiB/s, 4s left)`
+    pub fn description(&self) -> String {
+        let mut description = format_bytes(self.received_bytes);
+        if let Some(total_bytes) = self.total_bytes {
+            description = format!("{} / {}", description, format_bytes(total_bytes));
+        }
+        description = format!(
+            "{} ({}/s",
+            description,
+            format_bytes(self.bytes_per_second as u64)
+        );
+        if let Some(eta) = self.eta() {
+            description = format!("{}, {}s left", description, eta.as_secs());
+        }
+        format!("{})", description)
+    }
+}
+
+/// Progress of extracting an archive
+#[derive(Debug, Clone)]
+pub struct ExtractProgress {
+    /// Number of entries processed so far, including skipped ones
+    pub processed_entries: usize,
+    /// Number of entries in the archive, tar archives get scanned up front for this
+    pub total_entries: usize,
+    /// Name of the entry currently being processed
+    pub entry_name: String,
+}
+
+impl ExtractProgress {
+    /// Fraction of entries that are done
+    pub fn fraction(&self) -> f32 {
+        if self.total_entries == 0 {
+            return 0.0;
+        }
+        self.processed_entries as f32 / self.total_entries as f32
+    }
+
+    /// Human readable summary, e.g. `12 / 345: mods/Northstar.Client/mod.json`
+    pub fn description(&self) -> String {
+        format!(
+            "{} / {}: {}",
+            self.processed_entries, self.total_entries, self.entry_name
+        )
+    }
+}
+
+/// Formats a byte count using binary units
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+/// Updates sent from a running operation to the UI
+#[derive(Debug, Clone)]
+enum OperationUpdate {
+    Status(String),
+    Download(DownloadProgress),
+    Extract(ExtractProgress),
+    Report(String),
+    Finished(Result<(), String>),
+}
+
+/// Handle given to long-running operations to report their progress back to the UI
+#[derive(Clone)]
+pub struct OperationContext {
+    sender: Sender<OperationUpdate>,
+    egui_ctx: egui::Context,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl OperationContext {
+    /// Whether the user asked for the operation to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error if the operation was cancelled, to be called at points where it is safe to stop
+    pub fn check_cancelled(&self) -> Result<(), anyhow::Error> {
+        if self.is_cancelled() {
+            return Err(anyhow!("Operation cancelled"));
+        }
+        Ok(())
+    }
+
+    /// Reports what the operation is currently doing
+    pub fn status(&self, status: &str) {
+        println!("{}", status);
+        self.send(OperationUpdate::Status(status.to_string()));
+    }
+
+    /// Reports progress of the currently running download
+    pub fn download_progress(&self, progress: DownloadProgress) {
+        self.send(OperationUpdate::Download(progress));
+    }
+
+    /// Reports progress of the currently running extraction
+    pub fn extract_progress(&self, progress: ExtractProgress) {
+        self.send(OperationUpdate::Extract(progress));
+    }
+
+    /// Adds a line to the summary shown alongside the result of the operation
+    pub fn report(&self, line: &str) {
+        println!("{}", line);
+        self.send(OperationUpdate::Report(line.to_string()));
+    }
+
+    /// Context that isn't connected to any UI, for running steps of an operation in tests
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (sender, _) = channel();
+        Self {
+            sender,
+            egui_ctx: egui::Context::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn send(&self, update: OperationUpdate) {
+        // UI might have dropped the operation already, nothing left to report to then
+        let _ = self.sender.send(update);
+        self.egui_ctx.request_repaint();
+    }
+}
+
+/// An operation running on a background thread, as seen from the UI
+pub struct RunningOperation {
+    pub name: String,
+    pub status: String,
+    pub download: Option<DownloadProgress>,
+    pub extract: Option<ExtractProgress>,
+    /// Summary of what the operation did, e.g. which mods it installed
+    pub report: Vec<String>,
+    /// `None` while the operation is still running
+    pub result: Option<Result<(), String>>,
+    receiver: Receiver<OperationUpdate>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RunningOperation {
+    /// Runs `operation` on a background thread
+    pub fn spawn<F>(name: String, egui_ctx: &egui::Context, operation: F) -> Self
+    where
+        F: FnOnce(&OperationContext) -> Result<(), anyhow::Error> + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let operation_context = OperationContext {
+            sender,
+            egui_ctx: egui_ctx.clone(),
+            cancelled: cancelled.clone(),
+        };
+        std::thread::spawn(move || {
+            let result = operation(&operation_context).map_err(|err| {
+                println!("{}", err);
+                format!("{}", err)
+            });
+            operation_context.send(OperationUpdate::Finished(result));
+        });
+        Self {
+            name,
+            status: "Starting".to_string(),
+            download: None,
+            extract: None,
+            report: vec![],
+            result: None,
+            receiver,
+            cancelled,
+        }
+    }
+
+    /// Asks the operation to stop at the next safe point
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        !self.is_finished() && self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Applies all updates the operation sent since the last call
+    ///
+    /// Returns whether the operation finished during this call
+    pub fn poll(&mut self) -> bool {
+        let was_finished = self.is_finished();
+        loop {
+            let update = match self.receiver.try_recv() {
+                Ok(update) => update,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // Thread died without reporting back, e.g. due to a panic
+                    if self.result.is_none() {
+                        self.result = Some(Err("Operation stopped unexpectedly".to_string()));
+                    }
+                    break;
+                }
+            };
+            match update {
+                OperationUpdate::Status(status) => {
+                    self.status = status;
+                    // A new step means any previous download or extraction is done
+                    self.download = None;
+                    self.extract = None;
+                }
+                OperationUpdate::Download(progress) => self.download = Some(progress),
+                OperationUpdate::Extract(progress) => self.extract = Some(progress),
+                OperationUpdate::Report(line) => self.report.push(line),
+                OperationUpdate::Finished(result) => {
+                    self.download = None;
+                    self.extract = None;
+                    self.result = Some(result);
+                }
+            }
+        }
+        !was_finished && self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use super::operation::{format_bytes, OperationContext};
+
+/// Assumed archive size when the source doesn't tell us, e.g. for GitHub source archives
+const UNKNOWN_ARCHIVE_SIZE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How much larger extracted files are assumed to be than the archive they came from
+const EXTRACTION_FACTOR: u64 = 4;
+
+/// Bytes available to the current user on the volume containing `path`
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::useless_conversion)]
+    Ok(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+/// Bytes available to the current user on the volume containing `path`
+#[cfg(windows)]
+fn available_space(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        GetDiskFreeSpaceExW(
+            wide_path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { *available.QuadPart() })
+}
+
+/// Whether both paths are on the same volume and therefore share free space
+#[cfg(unix)]
+fn same_volume(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        // Assume the worst
+        _ => true,
+    }
+}
+
+/// Whether both paths are on the same volume and therefore share free space
+#[cfg(windows)]
+fn same_volume(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a.components().next() == b.components().next(),
+        // Assume the worst
+        _ => true,
+    }
+}
+
+/// Checks that files can be created in `dir` by creating and removing a probe file
+fn check_writable(dir: &Path) -> Result<(), anyhow::Error> {
+    match tempfile::Builder::new()
+        .prefix(".ns-dev-test-helper-write-check")
+        .tempfile_in(dir)
+    {
+        Ok(_) => Ok(()),
+        Err(err) => Err(anyhow!(
+            "Can't write to \"{}\": {}. Make sure the folder exists and your user has write access to it, \
+            e.g. by moving the game out of \"Program Files\" or changing the folder permissions.",
+            dir.display(),
+            err
+        )),
+    }
+}
+
+fn check_available_space(dir: &Path, required_bytes: u64) -> Result<(), anyhow::Error> {
+    let available_bytes = match available_space(dir) {
+        Ok(available_bytes) => available_bytes,
+        Err(err) => {
+            // Not knowing is no reason to refuse, the install will fail loudly if space runs out
+            println!(
+                "Couldn't determine free space of {}: {}",
+                dir.display(),
+                err
+            );
+            return Ok(());
+        }
+    };
+    if available_bytes < required_bytes {
+        return Err(anyhow!(
+            "Not enough free space for \"{}\": about {} needed but only {} available. \
+            Free up some space on that drive or reduce the download cache budget and try again.",
+            dir.display(),
+            format_bytes(required_bytes),
+            format_bytes(available_bytes)
+        ));
+    }
+    Ok(())
+}
+
+/// Checks before downloading that an archive of the given size can be downloaded and
+/// extracted into `staging_dir` and then installed into `game_install_path`
+///
+/// Sizes are estimates, `archive_size_bytes` is `None` if the source doesn't report one.
+pub fn check_install_preconditions(
+    staging_dir: &Path,
+    game_install_path: &Path,
+    archive_size_bytes: Option<u64>,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    operation.status("Checking free space and permissions");
+
+    check_writable(staging_dir)?;
+    check_writable(game_install_path)?;
+
+    let archive_size_bytes = archive_size_bytes.unwrap_or(UNKNOWN_ARCHIVE_SIZE_BYTES);
+    let extracted_size_bytes = archive_size_bytes.saturating_mul(EXTRACTION_FACTOR);
+    // Staging holds both the archive and its extracted files
+    let staging_bytes = archive_size_bytes.saturating_add(extracted_size_bytes);
+
+    if same_volume(staging_dir, game_install_path) {
+        check_available_space(
+            game_install_path,
+            staging_bytes.saturating_add(extracted_size_bytes),
+        )
+    } else {
+        check_available_space(staging_dir, staging_bytes)?;
+        check_available_space(game_install_path, extracted_size_bytes)
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::anyhow;
+
+/// Staging directories older than this are considered leftovers of a crashed run
+const STALE_STAGING_DIR_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Folder owned by the tool that holds the download cache, partial downloads and staging directories
+pub fn tool_folder() -> PathBuf {
+    match directories_next::ProjectDirs::from("", "", "northstar_dev_testing_helper_tool") {
+        Some(project_dirs) => project_dirs.cache_dir().to_path_buf(),
+        // No home folder to put our files into, fall back to system temp folder
+        None => std::env::temp_dir().join("northstar_dev_testing_helper_tool"),
+    }
+}
+
+/// Folder all per-operation staging directories are created in
+fn staging_root() -> PathBuf {
+    tool_folder().join("staging")
+}
+
+/// Unique directory for the temporary files of a single operation
+///
+/// The directory and everything in it is removed when this is dropped, which covers
+/// success, early returns on errors as well as unwinding panics.
+pub struct StagingDir {
+    dir: tempfile::TempDir,
+}
+
+impl StagingDir {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let root = staging_root();
+        fs::create_dir_all(&root)?;
+        match tempfile::Builder::new().prefix("op-").tempdir_in(&root) {
+            Ok(dir) => {
+                println!("Staging files in {}", dir.path().display());
+                Ok(Self { dir })
+            }
+            Err(err) => Err(anyhow!(
+                "Failed creating staging directory in {}: {}",
+                root.display(),
+                err
+            )),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Where the downloaded archive of this operation is stored
+    pub fn zip_path(&self) -> PathBuf {
+        self.dir.path().join("download.zip")
+    }
+}
+
+/// Removes staging directories left behind by runs that crashed or were killed
+///
+/// Only old directories are removed so that operations of other running instances are left alone.
+pub fn sweep_stale_staging_dirs() {
+    let entries = match fs::read_dir(staging_root()) {
+        Ok(entries) => entries,
+        // Nothing staged yet
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let is_stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map_or(false, |age| age > STALE_STAGING_DIR_AGE);
+        if is_stale {
+            println!("Removing stale staging dir {}", entry.path().display());
+            if let Err(err) = fs::remove_dir_all(entry.path()) {
+                println!("Failed removing {}: {}", entry.path().display(), err);
+            }
+        }
+    }
+}
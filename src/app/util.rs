@@ -1,15 +1,41 @@
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
-
-use reqwest::header::USER_AGENT;
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::anyhow;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use super::cache;
+use super::cache::cached_download_zip;
+use super::download::{download_zip, is_not_found, sha256_file, verify_zip, ExpectedDownload};
+use super::extract::{extract_zip, ArchiveSource};
+use super::filter::ExtractFilter;
+use super::http;
+use super::http::send_with_retry;
+use super::operation::OperationContext;
+use super::preflight::check_install_preconditions;
+use super::staging::StagingDir;
+use super::validate::{check_localisation_encodings, convert_to_utf16_le, validate_mod};
+
+/// Upstream repository that mods PRs are opened against
+const MODS_BASE_REPO: &str = "R2Northstar/NorthstarMods";
+
+/// Name of the profile folder that mods PRs get installed into
+const MANAGED_PROFILE_FOLDER: &str = "R2Northstar-PR-test-managed-folder";
+
+/// Profile Northstar uses by default, mods not picked from a PR are taken from here
+const BASE_PROFILE_FOLDER: &str = "R2Northstar";
+
+/// File inside the managed profile recording what was installed into it
+const INSTALL_RECORD_FILE: &str = "ns-dev-test-helper-install.json";
+
+/// File inside the game install recording where the installed launcher files came from
+const LAUNCHER_INSTALL_RECORD_FILE: &str = "ns-dev-test-helper-launcher-install.json";
 
 // GitHub API response JSON elements as structs
 #[derive(Debug, Deserialize, Clone)]
@@ -26,6 +52,7 @@ struct ActionsRunsResponse {
 #[derive(Debug, Deserialize, Clone)]
 struct Artifact {
     id: u64,
+    size_in_bytes: u64,
     workflow_run: WorkflowRun,
 }
 
@@ -39,7 +66,8 @@ struct CommitHead {
     sha: String,
     #[serde(rename = "ref")]
     gh_ref: String,
-    repo: Repo,
+    /// `None` if the fork the PR was opened from has been deleted
+    repo: Option<Repo>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -54,148 +82,200 @@ struct ArtifactsResponse {
     artifacts: Vec<Artifact>,
 }
 
-fn unzip(zip_file_name: &str) -> String {
-    let fname = std::path::Path::new(zip_file_name);
-    let file = fs::File::open(fname).unwrap();
-
-    let mut archive = zip::ZipArchive::new(file).unwrap();
-
-    let mut folder_name = "".to_string();
+#[derive(Debug, Deserialize, Clone)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+    size: u64,
+    /// Checksum like `sha256:<hex>`, only present on newer releases
+    digest: Option<String>,
+}
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
-        let outpath = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        };
+#[derive(Debug, Deserialize, Clone)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
 
-        {
-            let comment = file.comment();
-            if !comment.is_empty() {
-                println!("File {} comment: {}", i, comment);
-            }
-        }
+#[derive(Debug, Deserialize, Clone)]
+struct PullRequestFile {
+    filename: String,
+    status: String,
+    /// Only set for renamed files
+    previous_filename: Option<String>,
+}
 
-        if i == 0 {
-            // Sanity check that it's a folder
-            assert!((*file.name()).ends_with('/'));
+/// Where to download the source of a mods PR from
+#[derive(Debug, Clone)]
+struct ModsDownloadSource {
+    /// Commit SHA of the PR head at the time the PR list was fetched
+    head_sha: String,
+    /// Archive of exactly `head_sha`, `None` if the fork was deleted
+    pinned_download_url: Option<String>,
+    /// Archive of whatever the PR head currently points to, served from the base repo
+    pull_ref_download_url: String,
+}
 
-            folder_name = format!("{}", outpath.display());
-            println!("{}", folder_name);
-        }
+/// Where to download the CI build of a launcher PR from
+#[derive(Debug, Clone)]
+struct LauncherDownloadSource {
+    /// Commit SHA the CI build was made from
+    head_sha: String,
+    artifact_id: u64,
+    artifact_size_bytes: u64,
+    download_url: String,
+}
 
-        if (*file.name()).ends_with('/') {
-            // println!("File {} extracted to \"{}\"", i, outpath.display());
-            fs::create_dir_all(&outpath).unwrap();
-        } else {
-            // println!(
-            //     "File {} extracted to \"{}\" ({} bytes)",
-            //     i,
-            //     outpath.display(),
-            //     file.size()
-            // );
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
-                }
-            }
-            let mut outfile = fs::File::create(&outpath).unwrap();
-            io::copy(&mut file, &mut outfile).unwrap();
-        }
+/// Details about a single installed PR
+#[derive(Debug, Serialize, Clone)]
+struct InstalledPr {
+    pr_number: i64,
+    head_sha: String,
+    /// URL the archive was downloaded from, or `cache:<key>` if it was only ever found in the cache
+    source_url: String,
+    /// SHA-256 digest of the downloaded archive
+    archive_sha256: String,
+}
 
-        // Get and Set permissions
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+/// Details about an archive installed from disk instead of being downloaded
+#[derive(Debug, Serialize, Clone)]
+struct InstalledLocalZip {
+    path: String,
+    /// SHA-256 digest of the archive
+    archive_sha256: String,
+}
 
-            if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)).unwrap();
-            }
-        }
-    }
-    folder_name
+/// Record of what was installed into the managed profile, written next to the installed mods
+#[derive(Debug, Serialize, Clone)]
+struct InstallRecord {
+    launcher_pr: Option<InstalledPr>,
+    /// Mods PRs in the order they were layered into the profile
+    mods_prs: Vec<InstalledPr>,
+    /// Set if the profile was installed from a zip on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_mods_zip: Option<InstalledLocalZip>,
 }
 
-fn unzip_launcher_zip(zip_file_name: &str) -> String {
-    let outfolder_name = "ns-dev-test-helper-temp-pr-files";
-    let fname = std::path::Path::new(zip_file_name);
-    let file = fs::File::open(fname).unwrap();
+/// Details about an installed official release
+#[derive(Debug, Serialize, Clone)]
+struct InstalledRelease {
+    tag_name: String,
+    source_url: String,
+    /// SHA-256 digest of the downloaded archive
+    archive_sha256: String,
+}
 
-    let mut archive = zip::ZipArchive::new(file).unwrap();
+/// Record of where the launcher files in the game install came from, written into the game install
+///
+/// Only the field matching what was installed last is set.
+#[derive(Debug, Serialize, Clone, Default)]
+struct LauncherInstallRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    launcher_pr: Option<InstalledPr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release: Option<InstalledRelease>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_launcher_zip: Option<InstalledLocalZip>,
+}
 
-    fs::create_dir_all(outfolder_name).unwrap();
+/// Which files of a NorthstarMods source archive to extract, skipping repository files that are
+/// never part of a mod
+fn mods_extract_filter() -> ExtractFilter {
+    ExtractFilter::everything()
+        .strip_top_folder()
+        .exclude(".github/")
+        .exclude(".git*")
+        .exclude("*.md")
+}
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
-        let outpath = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        };
+/// Which files of a NorthstarLauncher CI artifact to install
+fn launcher_extract_filter() -> ExtractFilter {
+    ExtractFilter::everything()
+        .include("NorthstarLauncher.exe")
+        .include("Northstar.dll")
+}
 
-        {
-            let comment = file.comment();
-            if !comment.is_empty() {
-                println!("File {} comment: {}", i, comment);
-            }
-        }
+/// Extracts the files of an archive passing `filter` into `subfolder` of `destination` and
+/// returns that folder
+fn extract_to(
+    archive_source: &ArchiveSource,
+    destination: &Path,
+    subfolder: &str,
+    filter: &ExtractFilter,
+    operation: &OperationContext,
+) -> Result<PathBuf, anyhow::Error> {
+    let outfolder_name = destination.join(subfolder);
+    let extracted = extract_zip(archive_source, &outfolder_name, filter, operation)?;
+    println!(
+        "Extracted {} files from {}",
+        extracted.extracted_files, extracted.root
+    );
+    Ok(outfolder_name)
+}
 
-        // Only extract two hardcoded files
-        if *file.name() == *"NorthstarLauncher.exe" || *file.name() == *"Northstar.dll" {
-            println!(
-                "File {} extracted to \"{}\" ({} bytes)",
-                i,
-                outpath.display(),
-                file.size()
-            );
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
-                }
-            }
-            let mut outfile =
-                fs::File::create(format!("{}/{}", outfolder_name, outpath.display())).unwrap();
-            io::copy(&mut file, &mut outfile).unwrap();
-        }
+/// Reads the commit SHA from the comment of a GitHub source archive, if present
+fn zip_commit_sha(archive_source: &ArchiveSource) -> Option<String> {
+    let archive = zip::ZipArchive::new(archive_source.reader().ok()?).ok()?;
+    let comment = String::from_utf8_lossy(archive.comment())
+        .trim()
+        .to_string();
+    if comment.len() == 40 && comment.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(comment)
+    } else {
+        None
+    }
+}
 
-        // Get and Set permissions
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+/// Fetches a GitHub API endpoint, retrying transient failures
+///
+/// Can block for a while with backoff between attempts, so only call it from background operations.
+pub fn check_github_api(
+    url: &str,
+    operation: &OperationContext,
+) -> Result<serde_json::Value, Box<dyn Error>> {
+    fetch_github_api(url, Some(operation))
+}
 
-            if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)).unwrap();
-            }
-        }
-    }
-    outfolder_name.to_string()
+/// Fetches a GitHub API endpoint with a single attempt, for calls from the UI thread
+pub fn check_github_api_once(url: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+    fetch_github_api(url, None)
 }
 
-pub fn check_github_api(url: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+/// Fetches a GitHub API endpoint, only retrying for background operations as those can be cancelled
+fn fetch_github_api(
+    url: &str,
+    operation: Option<&OperationContext>,
+) -> Result<serde_json::Value, Box<dyn Error>> {
     println!("Checking GitHub API");
-    let user_agent = "GeckoEidechse/northstar-dev-testing-helper-tool";
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .get(url)
-        .header(USER_AGENT, user_agent)
-        .send()?
-        .text()?;
-
-    let json: serde_json::Value = serde_json::from_str(&res).expect("JSON was not well-formatted");
+    let client = http::client();
+    let request = client.get(url).build()?;
+    let resp = match operation {
+        Some(operation) => send_with_retry(&client, request, operation)?,
+        None => client.execute(request)?,
+    };
+    // Error pages, e.g. when rate limited, aren't the JSON we are looking for
+    let res = resp.error_for_status()?.text()?;
+
+    let json: serde_json::Value = serde_json::from_str(&res)?;
     println!("Done checking GitHub API");
 
     Ok(json)
 }
 
-/// Gets GitHub download link of a mods PR
+/// Cache key of the source archive of a mods commit
+fn mods_cache_key(commit_sha: &str) -> String {
+    format!("{}@{}", MODS_BASE_REPO, commit_sha)
+}
+
+/// Gets GitHub download links of a mods PR, pinned to the PR head commit
 fn get_mods_download_link(
     pr_number: i64,
     json_response: serde_json::Value,
-) -> Result<String, anyhow::Error> {
+) -> Result<ModsDownloadSource, anyhow::Error> {
     // {pr object} -> number == pr_number
-    //             -> head -> ref
+    //             -> head -> sha
     //                     -> repo -> full_name
-    let pulls_response: Vec<PullsApiResponseElement> =
-        serde_json::from_value(json_response).unwrap();
+    let pulls_response: Vec<PullsApiResponseElement> = serde_json::from_value(json_response)?;
 
     for pull_request in pulls_response {
         // Early return if PR number is not the right one
@@ -203,13 +283,33 @@ fn get_mods_download_link(
             continue;
         }
 
-        // Use repo and branch name to get download link
-        let download_url = format!(
-            "https://github.com/{}/archive/refs/heads/{}.zip",
-            pull_request.head.repo.full_name, // repo name
-            pull_request.head.gh_ref,         // branch name
+        println!(
+            "PR \"{}\" is branch \"{}\" at commit {}",
+            pr_number, pull_request.head.gh_ref, pull_request.head.sha
+        );
+
+        let pinned_download_url = match &pull_request.head.repo {
+            // Archive of the exact commit, unaffected by later force-pushes to the branch
+            Some(repo) => Some(format!(
+                "https://github.com/{}/archive/{}.zip",
+                repo.full_name,        // repo name
+                pull_request.head.sha, // commit sha
+            )),
+            None => {
+                println!("Fork of PR \"{}\" was deleted", pr_number);
+                None
+            }
+        };
+        // Pull refs live in the base repo and survive the fork being deleted
+        let pull_ref_download_url = format!(
+            "https://github.com/{}/archive/refs/pull/{}/head.zip",
+            MODS_BASE_REPO, pr_number,
         );
-        return Ok(download_url);
+        return Ok(ModsDownloadSource {
+            head_sha: pull_request.head.sha,
+            pinned_download_url,
+            pull_ref_download_url,
+        });
     }
     Err(anyhow!(
         "Couldn't grab download link for PR \"{}\"",
@@ -221,17 +321,18 @@ fn get_mods_download_link(
 fn get_launcher_download_link(
     pr_number: i64,
     json_response: serde_json::Value,
-) -> Result<String, anyhow::Error> {
+    operation: &OperationContext,
+) -> Result<LauncherDownloadSource, anyhow::Error> {
     // Crossreference with runs API
     let runs_response: ActionsRunsResponse = match check_github_api(
         "https://api.github.com/repos/R2Northstar/NorthstarLauncher/actions/runs",
+        operation,
     ) {
-        Ok(result) => serde_json::from_value(result).unwrap(),
+        Ok(result) => serde_json::from_value(result)?,
         Err(err) => return Err(anyhow!(format!("{}", err))),
     };
 
-    let pulls_response: Vec<PullsApiResponseElement> =
-        serde_json::from_value(json_response).unwrap();
+    let pulls_response: Vec<PullsApiResponseElement> = serde_json::from_value(json_response)?;
 
     // Get top commit SHA
     for pull_request in pulls_response {
@@ -248,8 +349,10 @@ fn get_launcher_download_link(
                 let api_url = format!("https://api.github.com/repos/R2Northstar/NorthstarLauncher/actions/runs/{}/artifacts", workflow_run.id);
                 println!("Checking: {}", api_url);
                 let artifacts_response: ArtifactsResponse =
-                    serde_json::from_value(check_github_api(&api_url).expect("Failed request"))
-                        .unwrap();
+                    match check_github_api(&api_url, operation) {
+                        Ok(result) => serde_json::from_value(result)?,
+                        Err(err) => return Err(anyhow!("Failed checking artifacts: {}", err)),
+                    };
 
                 // Iterate over artifacts
                 for artifact in artifacts_response.artifacts {
@@ -258,7 +361,12 @@ fn get_launcher_download_link(
                         dbg!(artifact.id);
 
                         // Download artifact
-                        return Ok(format!("https://nightly.link/R2Northstar/NorthstarLauncher/actions/artifacts/{}.zip", artifact.id));
+                        return Ok(LauncherDownloadSource {
+                            head_sha: workflow_run.head_sha.clone(),
+                            artifact_id: artifact.id,
+                            artifact_size_bytes: artifact.size_in_bytes,
+                            download_url: format!("https://nightly.link/R2Northstar/NorthstarLauncher/actions/artifacts/{}.zip", artifact.id),
+                        });
                     }
                 }
             }
@@ -270,38 +378,66 @@ fn get_launcher_download_link(
     ))
 }
 
-/// Downloads a file from given URL
-fn download_zip(download_url: String, location: String) -> Result<(), anyhow::Error> {
-    println!("Downloading file");
-    let user_agent = "GeckoEidechse/northstar-dev-testing-helper-tool";
-    let client = reqwest::blocking::Client::new();
-    let mut resp = match client
-        .get(download_url)
-        .header(USER_AGENT, user_agent)
-        .send()
-    {
-        Ok(result) => result,
-        Err(err) => return Err(anyhow!(format!("{}", err))),
-    };
+/// Gets the list of files a mods PR changes relative to its base branch
+fn get_mods_pr_changed_files(
+    pr_number: i64,
+    operation: &OperationContext,
+) -> Result<Vec<PullRequestFile>, anyhow::Error> {
+    let mut changed_files = vec![];
+    // API is paginated, keep fetching until we get a partial page
+    for page in 1.. {
+        let api_url = format!(
+            "https://api.github.com/repos/{}/pulls/{}/files?per_page=100&page={}",
+            MODS_BASE_REPO, pr_number, page
+        );
+        let files_page: Vec<PullRequestFile> = match check_github_api(&api_url, operation) {
+            Ok(result) => serde_json::from_value(result)?,
+            Err(err) => return Err(anyhow!(format!("{}", err))),
+        };
+        let page_len = files_page.len();
+        changed_files.extend(files_page);
+        if page_len < 100 {
+            break;
+        }
+    }
+    Ok(changed_files)
+}
 
-    // Error out earlier if non-successful response
-    if !resp.status().is_success() {
-        println!("Status: {}", resp.status());
-        // Return error cause wrong game path
-        return Err(anyhow!(
-            "Couldn't download zip. Received error code \"{}\"",
-            resp.status()
-        ));
+/// Finds files changed by more than one of the given PRs
+///
+/// Returns a map of file path to the numbers of the PRs changing it
+fn find_changed_file_conflicts(
+    changed_files_per_pr: &[(i64, Vec<PullRequestFile>)],
+) -> BTreeMap<String, Vec<i64>> {
+    let mut prs_per_file: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    for (pr_number, changed_files) in changed_files_per_pr {
+        for changed_file in changed_files {
+            // A rename touches both the old and the new path
+            let touched_paths = std::iter::once(&changed_file.filename)
+                .chain(changed_file.previous_filename.as_ref());
+            for touched_path in touched_paths {
+                let prs = prs_per_file.entry(touched_path.clone()).or_default();
+                if !prs.contains(pr_number) {
+                    prs.push(*pr_number);
+                }
+            }
+        }
     }
+    prs_per_file.retain(|_, prs| prs.len() > 1);
+    prs_per_file
+}
 
-    let mut out = File::create(format!("{}/ns-dev-test-helper-temp-pr-files.zip", location))
-        .expect("failed to create file");
-    io::copy(&mut resp, &mut out).expect("failed to copy content");
-    println!("Download done");
-    Ok(())
+/// Checks that a repo relative path from the GitHub API stays inside the folder it gets joined onto
+fn is_safe_relative_path(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
 }
 
 /// Recursively copies files from one directory to another
+///
+/// Used to write into the game install, so it deliberately ignores cancellation: stopping
+/// halfway would leave a mix of old and new files behind.
 fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
     fs::create_dir_all(&dst)?;
     for entry in fs::read_dir(src)? {
@@ -338,6 +474,60 @@ fn add_batch_file(game_install_path: &str) {
     }
 }
 
+/// Adds a batch file that allows for launching Northstar with the combination of PRs in a test set
+fn add_test_set_batch_file(
+    game_install_path: &str,
+    launcher_pr_number: Option<i64>,
+    mods_pr_numbers: &[i64],
+) -> Result<(), anyhow::Error> {
+    let batch_path = format!("{}/r2ns-launch-test-set.bat", game_install_path);
+    let launcher_pr_description = match launcher_pr_number {
+        Some(launcher_pr_number) => format!("NorthstarLauncher PR {}", launcher_pr_number),
+        None => "no NorthstarLauncher PR".to_string(),
+    };
+    let batch_file_content = format!(
+        "REM Test set: {} + NorthstarMods PRs {:?}\r\nNorthstarLauncher.exe -profile={}\r\n",
+        launcher_pr_description, mods_pr_numbers, MANAGED_PROFILE_FOLDER
+    );
+
+    match fs::write(&batch_path, batch_file_content) {
+        Ok(_) => println!("successfully wrote to {}", batch_path),
+        Err(err) => return Err(anyhow!("couldn't write to {}: {}", batch_path, err)),
+    }
+    Ok(())
+}
+
+/// Writes a record of the installed PRs into the managed profile folder
+fn write_install_record(
+    game_install_path: &str,
+    record: &InstallRecord,
+) -> Result<(), anyhow::Error> {
+    let record_path = format!(
+        "{}/{}/{}",
+        game_install_path, MANAGED_PROFILE_FOLDER, INSTALL_RECORD_FILE
+    );
+    let record_json = serde_json::to_string_pretty(record)?;
+    match fs::write(&record_path, record_json) {
+        Ok(_) => println!("Wrote install record to {}", record_path),
+        Err(err) => return Err(anyhow!("Failed writing install record: {}", err)),
+    }
+    Ok(())
+}
+
+/// Writes a record of the installed launcher files into game install path
+fn write_launcher_install_record(
+    game_install_path: &str,
+    record: &LauncherInstallRecord,
+) -> Result<(), anyhow::Error> {
+    let record_path = format!("{}/{}", game_install_path, LAUNCHER_INSTALL_RECORD_FILE);
+    let record_json = serde_json::to_string_pretty(record)?;
+    match fs::write(&record_path, record_json) {
+        Ok(_) => println!("Wrote launcher install record to {}", record_path),
+        Err(err) => return Err(anyhow!("Failed writing launcher install record: {}", err)),
+    }
+    Ok(())
+}
+
 /// Checks whether the provided path is a valid Titanfall2 gamepath by checking against a certain set of criteria
 fn check_game_path(game_install_path: &str) -> Result<(), anyhow::Error> {
     let is_correct_game_path =
@@ -389,47 +579,608 @@ pub fn find_game_install_path() -> Result<String, anyhow::Error> {
     ))
 }
 
-/// Downloads selected launcher PR and extracts it into game install path
-pub fn apply_launcher_pr(
-    pr_number: i64,
+/// Creates the staging directory for installing an archive of the given size into game install
+/// path, after checking there is enough space and write access for it
+///
+/// The directory is removed again once dropped, no matter how the install ends.
+fn prepare_staging(
     game_install_path: &str,
-    json_response: serde_json::Value,
-) -> Result<(), anyhow::Error> {
-    println!("{}", pr_number);
-    println!("{}", game_install_path);
+    archive_size_bytes: Option<u64>,
+    operation: &OperationContext,
+) -> Result<StagingDir, anyhow::Error> {
+    let staging_dir = StagingDir::new()?;
+    check_install_preconditions(
+        staging_dir.path(),
+        Path::new(game_install_path),
+        archive_size_bytes,
+        operation,
+    )?;
+    Ok(staging_dir)
+}
 
-    // Exit early if wrong game path
-    check_game_path(game_install_path)?;
+/// A PR downloaded and extracted into its own staging directory, ready to be installed
+struct StagedPr {
+    /// Kept alive until the PR is installed, removes the extracted files once dropped
+    _staging_dir: StagingDir,
+    extracted_folder: PathBuf,
+    installed_pr: InstalledPr,
+}
 
+/// Downloads launcher PR and extracts it into a staging directory
+fn stage_launcher_pr(
+    pr_number: i64,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<StagedPr, anyhow::Error> {
     // get download link
-    let download_url = get_launcher_download_link(pr_number, json_response)?;
+    let download_source = get_launcher_download_link(pr_number, json_response, operation)?;
 
-    println!("{}", download_url);
+    println!("{}", download_source.download_url);
+
+    let staging_dir = prepare_staging(
+        game_install_path,
+        Some(download_source.artifact_size_bytes),
+        operation,
+    )?;
 
     // download
-    download_zip(download_url, ".".to_string())?;
+    let downloaded = cached_download_zip(
+        &format!(
+            "R2Northstar/NorthstarLauncher/artifacts/{}",
+            download_source.artifact_id
+        ),
+        download_source.download_url.clone(),
+        &staging_dir.zip_path(),
+        // `size_in_bytes` of an artifact isn't necessarily the size of the zip nightly.link
+        // serves, so it is only good for estimating needed space
+        &ExpectedDownload::default(),
+        operation,
+    )?;
 
     // extract
-    let zip_extract_folder_name = unzip_launcher_zip("ns-dev-test-helper-temp-pr-files.zip");
+    operation.status("Extracting files");
+    let zip_extract_folder_name = extract_to(
+        &downloaded.source,
+        staging_dir.path(),
+        "launcher",
+        &launcher_extract_filter(),
+        operation,
+    )?;
 
     println!("Zip extract done");
 
-    println!("Deleting temp zip download folder");
+    Ok(StagedPr {
+        _staging_dir: staging_dir,
+        extracted_folder: zip_extract_folder_name,
+        installed_pr: InstalledPr {
+            pr_number,
+            head_sha: download_source.head_sha,
+            source_url: download_source.download_url,
+            archive_sha256: downloaded.sha256,
+        },
+    })
+}
 
-    fs::remove_file("ns-dev-test-helper-temp-pr-files.zip").unwrap();
+/// Copies a staged launcher PR into game install path
+fn install_staged_launcher_pr(
+    staged_pr: StagedPr,
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<InstalledPr, anyhow::Error> {
+    operation.status("Copying files to Titanfall2 install");
 
     // Copy downloaded folder to game install folder
-    match copy_dir_all(zip_extract_folder_name.clone(), game_install_path) {
-        Ok(_) => (),
-        Err(err) => {
-            return Err(anyhow!("Failed copying files: {}", err));
+    if let Err(err) = copy_dir_all(&staged_pr.extracted_folder, game_install_path) {
+        return Err(anyhow!("Failed copying files: {}", err));
+    }
+
+    Ok(staged_pr.installed_pr)
+}
+
+/// Downloads launcher PR and extracts it into game install path
+fn install_launcher_pr(
+    pr_number: i64,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<InstalledPr, anyhow::Error> {
+    let staged_pr = stage_launcher_pr(pr_number, game_install_path, json_response, operation)?;
+    install_staged_launcher_pr(staged_pr, game_install_path, operation)
+}
+
+/// Downloads and extracts the source of a mods PR into the given staging directory
+///
+/// Returns the folder the archive was extracted into alongside the details of what was downloaded
+fn download_mods_pr(
+    pr_number: i64,
+    json_response: serde_json::Value,
+    staging_dir: &StagingDir,
+    operation: &OperationContext,
+) -> Result<(PathBuf, InstalledPr), anyhow::Error> {
+    let download_source = get_mods_download_link(pr_number, json_response)?;
+    let zip_path = staging_dir.zip_path();
+
+    // Prefer the archive pinned to the commit, it can also be served from cache
+    // GitHub generates source archives on the fly, so there is no size or checksum to expect
+    let mut downloaded = None;
+    if let Some(download_url) = &download_source.pinned_download_url {
+        println!("{}", download_url);
+        match cached_download_zip(
+            &mods_cache_key(&download_source.head_sha),
+            download_url.clone(),
+            &zip_path,
+            &ExpectedDownload::default(),
+            operation,
+        ) {
+            Ok(archive) => downloaded = Some((download_url.clone(), archive)),
+            // Fork got deleted or made private after the PR list was fetched
+            Err(err) if is_not_found(&err) => {
+                println!("{} not found, falling back to pull ref", download_url)
+            }
+            // Pull ref might point to a newer commit, so don't silently install that instead
+            Err(err) => {
+                return Err(anyhow!(
+                    "Couldn't download PR \"{}\" at commit {}: {}",
+                    pr_number,
+                    download_source.head_sha,
+                    err
+                ))
+            }
+        }
+    }
+    // Without the fork there is no pinned archive, but the commit might have been cached before
+    if download_source.pinned_download_url.is_none() {
+        let cache_key = mods_cache_key(&download_source.head_sha);
+        if let Some(archive) = cache::get(
+            &cache_key,
+            &zip_path,
+            &ExpectedDownload::default(),
+            operation,
+        )? {
+            downloaded = Some((format!("cache:{}", cache_key), archive));
+        }
+    }
+    let downloading_pull_ref = downloaded.is_none();
+    let (downloaded_from, archive) = match downloaded {
+        Some(downloaded) => downloaded,
+        None => {
+            let download_url = download_source.pull_ref_download_url.clone();
+            println!("{}", download_url);
+            match download_zip(
+                download_url.clone(),
+                &zip_path,
+                &ExpectedDownload::default(),
+                operation,
+            ) {
+                Ok(archive) => (download_url, archive),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "Couldn't download PR \"{}\" from any source: {}",
+                        pr_number,
+                        err
+                    ))
+                }
+            }
+        }
+    };
+
+    // GitHub stores the commit SHA an archive was generated from as the zip comment
+    let installed_sha =
+        zip_commit_sha(&archive.source).unwrap_or_else(|| download_source.head_sha.clone());
+    if installed_sha != download_source.head_sha {
+        operation.report(&format!(
+            "PR {} was at commit {} when the PR list was fetched, but commit {} got installed",
+            pr_number, download_source.head_sha, installed_sha
+        ));
+    }
+
+    // Pull ref archives can only be cached once we know which commit they contain
+    if downloading_pull_ref {
+        if let Err(err) = cache::insert(&mods_cache_key(&installed_sha), &archive) {
+            println!("Failed caching PR \"{}\": {}", pr_number, err);
+        }
+    }
+
+    operation.status("Extracting files");
+    let zip_extract_folder_name = extract_to(
+        &archive.source,
+        staging_dir.path(),
+        "mods",
+        &mods_extract_filter(),
+        operation,
+    )?;
+
+    println!("Zip extract done");
+
+    Ok((
+        zip_extract_folder_name,
+        InstalledPr {
+            pr_number,
+            head_sha: installed_sha,
+            source_url: downloaded_from,
+            archive_sha256: archive.sha256,
+        },
+    ))
+}
+
+/// Names of the mods in an extracted archive, i.e. the folders in it that contain a `mod.json`
+fn find_mods(folder: &Path) -> Result<Vec<String>, anyhow::Error> {
+    let mut mod_names = vec![];
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && entry.path().join("mod.json").is_file() {
+            mod_names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    mod_names.sort();
+    Ok(mod_names)
+}
+
+/// Replaces the managed profile in game install path with the mods in the given folder
+///
+/// Only folders that are mods are installed, anything else in the archive like tooling or
+/// documentation is left out. If `selected_mods` is given, mods of the archive that aren't
+/// selected are copied from the base profile instead. Reports which mods were installed from
+/// where, with `source_name` describing the archive.
+fn install_mods_folder(
+    mods_folder: &Path,
+    source_name: &str,
+    selected_mods: Option<&[String]>,
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    let mod_names = find_mods(mods_folder)?;
+    if mod_names.is_empty() {
+        return Err(anyhow!(
+            "Archive doesn't contain any mods, i.e. folders with a mod.json"
+        ));
+    }
+    if let Some(selected_mods) = selected_mods {
+        if let Some(missing_mod) = selected_mods
+            .iter()
+            .find(|selected_mod| !mod_names.contains(selected_mod))
+        {
+            return Err(anyhow!(
+                "{} doesn't contain the selected mod {}",
+                source_name,
+                missing_mod
+            ));
+        }
+    }
+
+    // Delete previously managed folder
+    if std::fs::remove_dir_all(format!("{}/{}", game_install_path, MANAGED_PROFILE_FOLDER)).is_err()
+    {
+        if std::path::Path::new(&format!("{}/{}", game_install_path, MANAGED_PROFILE_FOLDER))
+            .exists()
+        {
+            println!("Failed removing previous dir"); // TODO check if exists and only panic if no exists
+        } else {
+            println!("Failed removing folder that doesn't exist. Probably cause first run");
+        }
+    };
+
+    operation.status("Copying files to Titanfall2 install");
+
+    // Copy mods from downloaded folder or base profile to game install folder
+    let mut installed_mods = vec![];
+    let mut base_profile_mods = vec![];
+    let mut missing_mods = vec![];
+    for mod_name in &mod_names {
+        let selected = selected_mods.map_or(true, |selected_mods| selected_mods.contains(mod_name));
+        let source_folder = if selected {
+            installed_mods.push(mod_name.as_str());
+            mods_folder.join(mod_name)
+        } else {
+            let base_profile_mod_folder = Path::new(game_install_path)
+                .join(BASE_PROFILE_FOLDER)
+                .join("mods")
+                .join(mod_name);
+            if !base_profile_mod_folder.is_dir() {
+                missing_mods.push(mod_name.as_str());
+                continue;
+            }
+            base_profile_mods.push(mod_name.as_str());
+            base_profile_mod_folder
+        };
+        if let Err(err) = copy_dir_all(
+            source_folder,
+            format!(
+                "{}/{}/mods/{}",
+                game_install_path, MANAGED_PROFILE_FOLDER, mod_name
+            ),
+        ) {
+            return Err(anyhow!("Failed copying {}: {}", mod_name, err));
+        }
+    }
+
+    operation.report(&format!(
+        "Installed mods from {}: {}",
+        source_name,
+        installed_mods.join(", ")
+    ));
+    if !base_profile_mods.is_empty() {
+        operation.report(&format!(
+            "Installed mods from {}: {}",
+            BASE_PROFILE_FOLDER,
+            base_profile_mods.join(", ")
+        ));
+    }
+    if !missing_mods.is_empty() {
+        operation.report(&format!(
+            "Not installed as they are missing from {}: {}",
+            BASE_PROFILE_FOLDER,
+            missing_mods.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks mod.json and referenced files of mods in the managed profile and reports any problems,
+/// so they show up before launching the game
+///
+/// Checks all mods in the profile unless only some are given in `mod_names`.
+fn report_mod_problems(
+    game_install_path: &str,
+    mod_names: Option<&[String]>,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    operation.status("Validating mods");
+
+    let profile_mods_folder = Path::new(game_install_path)
+        .join(MANAGED_PROFILE_FOLDER)
+        .join("mods");
+    let mod_names = match mod_names {
+        Some(mod_names) => mod_names.to_vec(),
+        None => find_mods(&profile_mods_folder)?,
+    };
+
+    let mut problems = vec![];
+    let mut has_encoding_problems = false;
+    for mod_name in &mod_names {
+        let mod_folder = profile_mods_folder.join(mod_name);
+        for problem in validate_mod(&mod_folder) {
+            problems.push(format!("{}: {}", mod_name, problem));
+        }
+        for encoding_problem in check_localisation_encodings(&mod_folder) {
+            has_encoding_problems = true;
+            problems.push(format!(
+                "{}: {} is {} but has to be UTF-16 LE with BOM",
+                mod_name,
+                encoding_problem
+                    .path
+                    .strip_prefix(&mod_folder)
+                    .unwrap_or(&encoding_problem.path)
+                    .display(),
+                encoding_problem.encoding
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        operation.report(&format!(
+            "Validated {} mods, no problems found",
+            mod_names.len()
+        ));
+    } else {
+        operation.report(&format!(
+            "Found {} problems in mods, these will likely break the game:",
+            problems.len()
+        ));
+        for problem in &problems {
+            operation.report(problem);
+        }
+        if has_encoding_problems {
+            operation.report(
+                "Use \"Convert localisation files\" to fix the encoding in the test profile",
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Converts localisation files of mods in the managed profile to the UTF-16 LE with BOM the game
+/// expects
+///
+/// Only touches the test profile, the PR itself still needs fixing.
+pub fn convert_localisation_files(
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    operation.status("Converting localisation files");
+
+    let profile_mods_folder = Path::new(game_install_path)
+        .join(MANAGED_PROFILE_FOLDER)
+        .join("mods");
+    if !profile_mods_folder.is_dir() {
+        return Err(anyhow!(
+            "No test profile installed in \"{}\"",
+            game_install_path
+        ));
+    }
+
+    let mut converted_files = 0;
+    for mod_name in find_mods(&profile_mods_folder)? {
+        operation.check_cancelled()?;
+
+        let mod_folder = profile_mods_folder.join(&mod_name);
+        for encoding_problem in check_localisation_encodings(&mod_folder) {
+            let relative_path = encoding_problem
+                .path
+                .strip_prefix(&mod_folder)
+                .unwrap_or(&encoding_problem.path)
+                .display()
+                .to_string();
+            match convert_to_utf16_le(&encoding_problem.path) {
+                Ok(()) => {
+                    converted_files += 1;
+                    operation.report(&format!(
+                        "{}: converted {} from {}",
+                        mod_name, relative_path, encoding_problem.encoding
+                    ));
+                }
+                Err(err) => operation.report(&format!("{}: {}: {}", mod_name, relative_path, err)),
+            }
+        }
+    }
+
+    operation.report(&format!("Converted {} localisation files", converted_files));
+    Ok(())
+}
+
+/// Downloads mods PR and extracts it into a staging directory
+fn stage_mods_pr(
+    pr_number: i64,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<StagedPr, anyhow::Error> {
+    let staging_dir = prepare_staging(game_install_path, None, operation)?;
+    let (zip_extract_folder_name, installed_pr) =
+        download_mods_pr(pr_number, json_response, &staging_dir, operation)?;
+    Ok(StagedPr {
+        _staging_dir: staging_dir,
+        extracted_folder: zip_extract_folder_name,
+        installed_pr,
+    })
+}
+
+/// Installs a staged mods PR into a freshly cleared managed profile in game install path
+///
+/// Only `selected_mods` are installed from the PR if given, see `install_mods_folder`.
+fn install_staged_mods_pr(
+    staged_pr: StagedPr,
+    selected_mods: Option<&[String]>,
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<InstalledPr, anyhow::Error> {
+    install_mods_folder(
+        &staged_pr.extracted_folder,
+        &format!("PR {}", staged_pr.installed_pr.pr_number),
+        selected_mods,
+        game_install_path,
+        operation,
+    )?;
+
+    Ok(staged_pr.installed_pr)
+}
+
+/// Downloads mods PR and extracts it into a freshly cleared managed profile in game install path
+///
+/// Only `selected_mods` are installed from the PR if given, see `install_mods_folder`.
+fn install_mods_pr(
+    pr_number: i64,
+    selected_mods: Option<&[String]>,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<InstalledPr, anyhow::Error> {
+    let staged_pr = stage_mods_pr(pr_number, game_install_path, json_response, operation)?;
+    install_staged_mods_pr(staged_pr, selected_mods, game_install_path, operation)
+}
+
+/// Copies the given changed files of a PR from its extracted archive into a mods folder
+///
+/// Not cancellable, stopping halfway would leave the profile with only some of the changes.
+fn copy_changed_files(
+    source_folder: &Path,
+    mods_folder: &str,
+    changed_files: &[PullRequestFile],
+) -> Result<(), anyhow::Error> {
+    let mod_names = find_mods(source_folder)?;
+    for changed_file in changed_files {
+        // Not part of any mod, so it was never installed in the first place
+        let in_mod = Path::new(&changed_file.filename)
+            .components()
+            .next()
+            .map_or(false, |component| {
+                mod_names
+                    .iter()
+                    .any(|mod_name| component.as_os_str() == mod_name.as_str())
+            });
+        if !in_mod {
+            println!("Skipping {}", changed_file.filename);
+            continue;
+        }
+
+        if !is_safe_relative_path(&changed_file.filename) {
+            return Err(anyhow!(
+                "Refusing to install unexpected path \"{}\"",
+                changed_file.filename
+            ));
+        }
+
+        // Renamed files leave their old path behind
+        if let Some(previous_filename) = &changed_file.previous_filename {
+            if is_safe_relative_path(previous_filename) {
+                let _ = fs::remove_file(format!("{}/{}", mods_folder, previous_filename));
+            }
+        }
+
+        let destination = format!("{}/{}", mods_folder, changed_file.filename);
+        if changed_file.status == "removed" {
+            let _ = fs::remove_file(&destination);
+            continue;
+        }
+
+        if let Some(parent) = Path::new(&destination).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if let Err(err) = fs::copy(source_folder.join(&changed_file.filename), &destination) {
+            return Err(anyhow!(
+                "Failed copying \"{}\": {}",
+                changed_file.filename,
+                err
+            ));
         }
     }
+    Ok(())
+}
+
+/// Layers only the files a staged mods PR changes on top of the managed profile
+fn layer_staged_mods_pr(
+    staged_pr: StagedPr,
+    game_install_path: &str,
+    changed_files: &[PullRequestFile],
+    operation: &OperationContext,
+) -> Result<InstalledPr, anyhow::Error> {
+    let mods_folder = format!("{}/{}/mods", game_install_path, MANAGED_PROFILE_FOLDER);
 
-    println!("Deleting old unzipped folder");
+    operation.status(&format!(
+        "Layering changed files of PR {} onto profile",
+        staged_pr.installed_pr.pr_number
+    ));
 
-    // Delete old copy
-    std::fs::remove_dir_all(zip_extract_folder_name).unwrap();
+    copy_changed_files(&staged_pr.extracted_folder, &mods_folder, changed_files)?;
+
+    Ok(staged_pr.installed_pr)
+}
+
+/// Downloads selected launcher PR and extracts it into game install path
+pub fn apply_launcher_pr(
+    pr_number: i64,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    println!("{}", pr_number);
+    println!("{}", game_install_path);
+
+    // Exit early if wrong game path
+    check_game_path(game_install_path)?;
+
+    let installed_launcher_pr =
+        install_launcher_pr(pr_number, game_install_path, json_response, operation)?;
+
+    write_launcher_install_record(
+        game_install_path,
+        &LauncherInstallRecord {
+            launcher_pr: Some(installed_launcher_pr),
+            ..Default::default()
+        },
+    )?;
 
     println!("All done :D");
 
@@ -441,6 +1192,44 @@ pub fn apply_mods_pr(
     pr_number: i64,
     game_install_path: &str,
     json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    apply_mods_pr_selection(pr_number, None, game_install_path, json_response, operation)
+}
+
+/// Downloads a mods PR and lists the mods it contains, so some of them can be picked for
+/// `apply_mods_pr_selection`
+pub fn list_mods_pr(
+    pr_number: i64,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<Vec<String>, anyhow::Error> {
+    let staging_dir = prepare_staging(game_install_path, None, operation)?;
+    // Also puts the archive into the cache, so installing doesn't download it again
+    let (zip_extract_folder_name, _) =
+        download_mods_pr(pr_number, json_response, &staging_dir, operation)?;
+
+    let mod_names = find_mods(&zip_extract_folder_name)?;
+    if mod_names.is_empty() {
+        return Err(anyhow!(
+            "PR {} doesn't contain any mods, i.e. folders with a mod.json",
+            pr_number
+        ));
+    }
+    Ok(mod_names)
+}
+
+/// Downloads selected mods PR and extracts it into profile in game install path
+///
+/// If `selected_mods` is given only those are installed from the PR, the other mods of the PR
+/// are copied from the base profile instead.
+pub fn apply_mods_pr_selection(
+    pr_number: i64,
+    selected_mods: Option<&[String]>,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
 ) -> Result<(), anyhow::Error> {
     println!("{}", pr_number);
     println!("{}", game_install_path);
@@ -448,59 +1237,370 @@ pub fn apply_mods_pr(
     // Exit early if wrong game path
     check_game_path(game_install_path)?;
 
-    let download_url = get_mods_download_link(pr_number, json_response)?;
+    let installed_mods_pr = install_mods_pr(
+        pr_number,
+        selected_mods,
+        game_install_path,
+        json_response,
+        operation,
+    )?;
+
+    report_mod_problems(game_install_path, selected_mods, operation)?;
+
+    write_install_record(
+        game_install_path,
+        &InstallRecord {
+            launcher_pr: None,
+            mods_prs: vec![installed_mods_pr],
+            local_mods_zip: None,
+        },
+    )?;
 
-    println!("{}", download_url);
+    println!("Adding batch file to 1-click-run PR");
 
-    download_zip(download_url, ".".to_string())?;
+    add_batch_file(game_install_path);
 
-    let zip_extract_folder_name = unzip("ns-dev-test-helper-temp-pr-files.zip");
+    println!("All done :D");
 
-    println!("Zip extract done");
+    Ok(())
+}
 
-    println!("Deleting temp zip download folder");
+/// Installs a test set of an optional launcher PR and any number of stacked mods PRs
+/// and adds a batch file to launch that combination
+///
+/// Mods PRs are layered in order: the first one is installed in full, later ones only
+/// contribute the files they change. Files changed by more than one mods PR are
+/// reported as an error unless `allow_conflicts` is set, in which case the last PR wins.
+pub fn apply_test_set(
+    launcher_pr_number: Option<i64>,
+    mods_pr_numbers: &[i64],
+    allow_conflicts: bool,
+    game_install_path: &str,
+    launcher_json_response: serde_json::Value,
+    mods_json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    println!(
+        "Test set: launcher PR {:?} + mods PRs {:?}",
+        launcher_pr_number, mods_pr_numbers
+    );
+    println!("{}", game_install_path);
 
-    fs::remove_file("ns-dev-test-helper-temp-pr-files.zip").unwrap();
+    // Exit early if wrong game path
+    check_game_path(game_install_path)?;
 
-    // TODO: delete downloaded zip folder again here
+    // The test set profile is made up of mods PRs
+    if mods_pr_numbers.is_empty() {
+        return Err(anyhow!("Test set needs at least one mods PR"));
+    }
 
-    // Delete previously managed folder
-    if std::fs::remove_dir_all(format!(
-        "{}/R2Northstar-PR-test-managed-folder",
-        game_install_path
-    ))
-    .is_err()
-    {
-        if std::path::Path::new(&format!(
-            "{}/R2Northstar-PR-test-managed-folder",
-            game_install_path
-        ))
-        .exists()
-        {
-            println!("Failed removing previous dir"); // TODO check if exists and only panic if no exists
+    // Check for overlapping changes before touching anything
+    let mut changed_files_per_pr = vec![];
+    if mods_pr_numbers.len() > 1 {
+        operation.status("Checking mods PRs for conflicting changes");
+        for mods_pr_number in mods_pr_numbers {
+            changed_files_per_pr.push((
+                *mods_pr_number,
+                get_mods_pr_changed_files(*mods_pr_number, operation)?,
+            ));
+        }
+        let conflicts = find_changed_file_conflicts(&changed_files_per_pr);
+        if !conflicts.is_empty() {
+            operation.report("Files changed by more than one mods PR:");
+            for (path, prs) in &conflicts {
+                operation.report(&format!("{} (PRs {:?})", path, prs));
+            }
+            if !allow_conflicts {
+                return Err(anyhow!(
+                    "Mods PRs change the same {} files",
+                    conflicts.len()
+                ));
+            }
+            operation.report("Installing anyway, files of later PRs win");
+        }
+    }
+
+    // Download everything before touching the game install, so cancelling can't leave it with
+    // only part of the test set
+    let staged_launcher_pr = match launcher_pr_number {
+        Some(launcher_pr_number) => Some(stage_launcher_pr(
+            launcher_pr_number,
+            game_install_path,
+            launcher_json_response,
+            operation,
+        )?),
+        None => None,
+    };
+    let mut staged_mods_prs = vec![];
+    for mods_pr_number in mods_pr_numbers {
+        staged_mods_prs.push(stage_mods_pr(
+            *mods_pr_number,
+            game_install_path,
+            mods_json_response.clone(),
+            operation,
+        )?);
+    }
+
+    let installed_launcher_pr = match staged_launcher_pr {
+        Some(staged_pr) => Some(install_staged_launcher_pr(
+            staged_pr,
+            game_install_path,
+            operation,
+        )?),
+        None => None,
+    };
+
+    let mut installed_mods_prs = vec![];
+    for (i, staged_pr) in staged_mods_prs.into_iter().enumerate() {
+        let installed_mods_pr = if i == 0 {
+            install_staged_mods_pr(staged_pr, None, game_install_path, operation)?
         } else {
-            println!("Failed removing folder that doesn't exist. Probably cause first run");
+            layer_staged_mods_pr(
+                staged_pr,
+                game_install_path,
+                &changed_files_per_pr[i].1,
+                operation,
+            )?
+        };
+        installed_mods_prs.push(installed_mods_pr);
+    }
+
+    report_mod_problems(game_install_path, None, operation)?;
+
+    if let Some(installed_launcher_pr) = &installed_launcher_pr {
+        write_launcher_install_record(
+            game_install_path,
+            &LauncherInstallRecord {
+                launcher_pr: Some(installed_launcher_pr.clone()),
+                ..Default::default()
+            },
+        )?;
+    }
+
+    write_install_record(
+        game_install_path,
+        &InstallRecord {
+            launcher_pr: installed_launcher_pr,
+            mods_prs: installed_mods_prs,
+            local_mods_zip: None,
+        },
+    )?;
+
+    println!("Adding batch file to 1-click-run test set");
+
+    add_test_set_batch_file(game_install_path, launcher_pr_number, mods_pr_numbers)?;
+
+    println!("All done :D");
+
+    Ok(())
+}
+
+/// Downloads selected official Northstar release and extracts it into game install path
+pub fn apply_release(
+    tag_name: &str,
+    game_install_path: &str,
+    json_response: serde_json::Value,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    println!("{}", tag_name);
+    println!("{}", game_install_path);
+
+    // Exit early if wrong game path
+    check_game_path(game_install_path)?;
+
+    let releases_response: Vec<Release> = serde_json::from_value(json_response)?;
+    let release = match releases_response
+        .into_iter()
+        .find(|release| release.tag_name == tag_name)
+    {
+        Some(release) => release,
+        None => return Err(anyhow!("Couldn't find release \"{}\"", tag_name)),
+    };
+
+    // Release zip is named like `Northstar.release.v1.2.3.zip`
+    let asset = match release
+        .assets
+        .iter()
+        .find(|asset| asset.name.starts_with("Northstar.") && asset.name.ends_with(".zip"))
+    {
+        Some(asset) => asset,
+        None => {
+            return Err(anyhow!(
+                "Release \"{}\" has no Northstar zip to download",
+                tag_name
+            ))
         }
     };
 
-    println!("Copying files to Titanfall2 install");
+    println!("{}", asset.browser_download_url);
+
+    let staging_dir = prepare_staging(game_install_path, Some(asset.size), operation)?;
+
+    let downloaded = cached_download_zip(
+        &format!("R2Northstar/Northstar/releases/{}", tag_name),
+        asset.browser_download_url.clone(),
+        &staging_dir.zip_path(),
+        &ExpectedDownload {
+            size_bytes: Some(asset.size),
+            sha256: asset
+                .digest
+                .as_ref()
+                .and_then(|digest| digest.strip_prefix("sha256:"))
+                .map(|sha256| sha256.to_string()),
+        },
+        operation,
+    )?;
+
+    operation.status("Extracting files");
+    let zip_extract_folder_name = extract_to(
+        &downloaded.source,
+        staging_dir.path(),
+        "release",
+        &ExtractFilter::everything(),
+        operation,
+    )?;
 
-    // Copy downloaded folder to game install folder
-    copy_dir_all(
-        zip_extract_folder_name.clone(),
-        format!(
-            "{}/R2Northstar-PR-test-managed-folder/mods",
-            game_install_path
-        ),
-    )
-    .unwrap();
+    println!("Zip extract done");
 
-    println!("Deleting old unzipped folder");
+    operation.status("Copying files to Titanfall2 install");
 
-    // Delete old copy
-    std::fs::remove_dir_all(zip_extract_folder_name).unwrap();
+    // Copy release files over the existing install, this includes the default `R2Northstar` profile
+    if let Err(err) = copy_dir_all(zip_extract_folder_name, game_install_path) {
+        return Err(anyhow!("Failed copying files: {}", err));
+    }
 
-    println!("Adding batch file to 1-click-run PR");
+    write_launcher_install_record(
+        game_install_path,
+        &LauncherInstallRecord {
+            release: Some(InstalledRelease {
+                tag_name: tag_name.to_string(),
+                source_url: asset.browser_download_url.clone(),
+                archive_sha256: downloaded.sha256,
+            }),
+            ..Default::default()
+        },
+    )?;
+
+    println!("All done :D");
+
+    Ok(())
+}
+
+/// Checks that a zip picked by the user can be installed and returns its size in bytes
+fn check_local_zip(zip_path: &Path) -> Result<u64, anyhow::Error> {
+    let metadata = match fs::metadata(zip_path) {
+        Ok(metadata) => metadata,
+        Err(err) => return Err(anyhow!("Couldn't open \"{}\": {}", zip_path.display(), err)),
+    };
+    if let Err(err) = verify_zip(&ArchiveSource::File(zip_path.to_path_buf())) {
+        return Err(anyhow!("\"{}\": {}", zip_path.display(), err));
+    }
+    Ok(metadata.len())
+}
+
+/// Installs a launcher build from a zip on disk, e.g. a CI artifact downloaded in the browser
+pub fn apply_local_launcher_zip(
+    zip_path: &Path,
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    println!("{}", zip_path.display());
+    println!("{}", game_install_path);
+
+    // Exit early if wrong game path
+    check_game_path(game_install_path)?;
+
+    let zip_size_bytes = check_local_zip(zip_path)?;
+
+    let staging_dir = prepare_staging(game_install_path, Some(zip_size_bytes), operation)?;
+
+    let archive_sha256 = sha256_file(zip_path)?;
+
+    operation.status("Extracting files");
+    let zip_extract_folder_name = extract_to(
+        &ArchiveSource::File(zip_path.to_path_buf()),
+        staging_dir.path(),
+        "launcher",
+        &launcher_extract_filter(),
+        operation,
+    )?;
+
+    println!("Zip extract done");
+
+    // Copy extracted folder to game install folder
+    if let Err(err) = copy_dir_all(zip_extract_folder_name, game_install_path) {
+        return Err(anyhow!("Failed copying files: {}", err));
+    }
+
+    write_launcher_install_record(
+        game_install_path,
+        &LauncherInstallRecord {
+            local_launcher_zip: Some(InstalledLocalZip {
+                path: zip_path.display().to_string(),
+                archive_sha256,
+            }),
+            ..Default::default()
+        },
+    )?;
+
+    println!("All done :D");
+
+    Ok(())
+}
+
+/// Installs mods from a zip on disk into profile in game install path, e.g. a source archive
+/// downloaded in the browser
+pub fn apply_local_mods_zip(
+    zip_path: &Path,
+    game_install_path: &str,
+    operation: &OperationContext,
+) -> Result<(), anyhow::Error> {
+    println!("{}", zip_path.display());
+    println!("{}", game_install_path);
+
+    // Exit early if wrong game path
+    check_game_path(game_install_path)?;
+
+    let zip_size_bytes = check_local_zip(zip_path)?;
+
+    let staging_dir = prepare_staging(game_install_path, Some(zip_size_bytes), operation)?;
+
+    let archive_sha256 = sha256_file(zip_path)?;
+
+    operation.status("Extracting files");
+    let zip_extract_folder_name = extract_to(
+        &ArchiveSource::File(zip_path.to_path_buf()),
+        staging_dir.path(),
+        "mods",
+        &mods_extract_filter(),
+        operation,
+    )?;
+
+    println!("Zip extract done");
+
+    install_mods_folder(
+        &zip_extract_folder_name,
+        &zip_path.display().to_string(),
+        None,
+        game_install_path,
+        operation,
+    )?;
+
+    report_mod_problems(game_install_path, None, operation)?;
+
+    write_install_record(
+        game_install_path,
+        &InstallRecord {
+            launcher_pr: None,
+            mods_prs: vec![],
+            local_mods_zip: Some(InstalledLocalZip {
+                path: zip_path.display().to_string(),
+                archive_sha256,
+            }),
+        },
+    )?;
+
+    println!("Adding batch file to 1-click-run local mods");
 
     add_batch_file(game_install_path);
 
@@ -508,3 +1608,125 @@ pub fn apply_mods_pr(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed_file(
+        filename: &str,
+        status: &str,
+        previous_filename: Option<&str>,
+    ) -> PullRequestFile {
+        PullRequestFile {
+            filename: filename.to_string(),
+            status: status.to_string(),
+            previous_filename: previous_filename.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn conflicts_include_files_changed_by_several_prs() {
+        let conflicts = find_changed_file_conflicts(&[
+            (1, vec![changed_file("Mod/a.nut", "modified", None)]),
+            (2, vec![changed_file("Mod/a.nut", "modified", None)]),
+            (3, vec![changed_file("Mod/b.nut", "modified", None)]),
+        ]);
+        assert_eq!(
+            conflicts,
+            BTreeMap::from([("Mod/a.nut".to_string(), vec![1, 2])])
+        );
+    }
+
+    #[test]
+    fn conflicts_include_previous_path_of_renames() {
+        let conflicts = find_changed_file_conflicts(&[
+            (1, vec![changed_file("Mod/old.nut", "modified", None)]),
+            (
+                2,
+                vec![changed_file("Mod/new.nut", "renamed", Some("Mod/old.nut"))],
+            ),
+        ]);
+        assert_eq!(
+            conflicts,
+            BTreeMap::from([("Mod/old.nut".to_string(), vec![1, 2])])
+        );
+    }
+
+    #[test]
+    fn conflicts_count_each_pr_once() {
+        let conflicts = find_changed_file_conflicts(&[(
+            1,
+            vec![
+                changed_file("Mod/a.nut", "renamed", Some("Mod/b.nut")),
+                changed_file("Mod/b.nut", "added", None),
+            ],
+        )]);
+        assert!(conflicts.is_empty());
+    }
+
+    /// Creates a mod called `Mod` with the given files in `folder`
+    fn write_mod(folder: &Path, files: &[&str]) {
+        fs::create_dir_all(folder.join("Mod")).unwrap();
+        fs::write(folder.join("Mod/mod.json"), "{}").unwrap();
+        for file in files {
+            fs::write(folder.join(file), *file).unwrap();
+        }
+    }
+
+    #[test]
+    fn copy_changed_files_removes_removed_files() {
+        let source = tempfile::tempdir().unwrap();
+        let mods = tempfile::tempdir().unwrap();
+        write_mod(source.path(), &[]);
+        write_mod(mods.path(), &["Mod/gone.nut", "Mod/kept.nut"]);
+
+        copy_changed_files(
+            source.path(),
+            mods.path().to_str().unwrap(),
+            &[changed_file("Mod/gone.nut", "removed", None)],
+        )
+        .unwrap();
+
+        assert!(!mods.path().join("Mod/gone.nut").exists());
+        assert!(mods.path().join("Mod/kept.nut").exists());
+    }
+
+    #[test]
+    fn copy_changed_files_moves_renamed_files() {
+        let source = tempfile::tempdir().unwrap();
+        let mods = tempfile::tempdir().unwrap();
+        write_mod(source.path(), &["Mod/new.nut"]);
+        write_mod(mods.path(), &["Mod/old.nut"]);
+
+        copy_changed_files(
+            source.path(),
+            mods.path().to_str().unwrap(),
+            &[changed_file("Mod/new.nut", "renamed", Some("Mod/old.nut"))],
+        )
+        .unwrap();
+
+        assert!(!mods.path().join("Mod/old.nut").exists());
+        assert_eq!(
+            fs::read_to_string(mods.path().join("Mod/new.nut")).unwrap(),
+            "Mod/new.nut"
+        );
+    }
+
+    #[test]
+    fn copy_changed_files_skips_files_outside_mods() {
+        let source = tempfile::tempdir().unwrap();
+        let mods = tempfile::tempdir().unwrap();
+        write_mod(source.path(), &[]);
+        fs::write(source.path().join("README.md"), "readme").unwrap();
+
+        copy_changed_files(
+            source.path(),
+            mods.path().to_str().unwrap(),
+            &[changed_file("README.md", "modified", None)],
+        )
+        .unwrap();
+
+        assert!(!mods.path().join("README.md").exists());
+    }
+}
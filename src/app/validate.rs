@@ -0,0 +1,427 @@
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+/// Fields every mod.json needs, all of them strings
+///
+/// Northstar defaults `Description` and `Version` if they are missing, so those are optional.
+const REQUIRED_STRING_FIELDS: [&str; 1] = ["Name"];
+
+/// Folder inside a mod that script paths in mod.json are relative to
+const SCRIPTS_FOLDER: &str = "mod/scripts/vscripts";
+
+/// Folder inside a mod that localisation paths in mod.json are relative to
+const LOCALISATION_FOLDER: &str = "mod";
+
+/// Language Northstar falls back to if a localisation file doesn't exist for the game's language
+const FALLBACK_LANGUAGE: &str = "english";
+
+/// Removes comments and trailing commas, which Northstar accepts in mod.json but JSON doesn't
+fn strip_json_extensions(json: &str) -> String {
+    let mut stripped = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        stripped.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (']' | '}', _) => {
+                // Comma followed by nothing but whitespace before the closing bracket
+                let trimmed_len = stripped.trim_end().len();
+                if stripped[..trimmed_len].ends_with(',') {
+                    stripped.remove(trimmed_len - 1);
+                }
+                stripped.push(c);
+            }
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+/// Whether a file exists at `relative_path` inside `folder`, ignoring case like the game does on
+/// Windows
+fn file_exists(folder: &Path, relative_path: &str) -> bool {
+    let mut path = folder.to_path_buf();
+    for component in Path::new(relative_path).components() {
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy().to_lowercase(),
+            // Paths in mod.json must not leave the mod
+            _ => return false,
+        };
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+        match entries
+            .filter_map(Result::ok)
+            .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == name)
+        {
+            Some(entry) => path = entry.path(),
+            None => return false,
+        }
+    }
+    path.is_file()
+}
+
+/// Checks the mod.json of the mod in `mod_folder` and that the files it references exist
+///
+/// Returns a description of every problem found, empty if the mod looks fine.
+pub fn validate_mod(mod_folder: &Path) -> Vec<String> {
+    let mod_json = match fs::read_to_string(mod_folder.join("mod.json")) {
+        Ok(mod_json) => mod_json,
+        Err(err) => return vec![format!("Couldn't read mod.json: {}", err)],
+    };
+    let mod_json: Value = match serde_json::from_str(&strip_json_extensions(&mod_json)) {
+        Ok(mod_json) => mod_json,
+        Err(err) => return vec![format!("mod.json is not valid JSON: {}", err)],
+    };
+    if !mod_json.is_object() {
+        return vec!["mod.json is not an object".to_string()];
+    }
+
+    let mut problems = vec![];
+    for field in REQUIRED_STRING_FIELDS {
+        if !mod_json.get(field).map_or(false, Value::is_string) {
+            problems.push(format!("mod.json has no \"{}\" text", field));
+        }
+    }
+    if let Some(load_priority) = mod_json.get("LoadPriority") {
+        if !load_priority.is_i64() {
+            problems.push("\"LoadPriority\" is not a whole number".to_string());
+        }
+    }
+
+    match mod_json.get("Scripts") {
+        None => {}
+        Some(Value::Array(scripts)) => {
+            for (index, script) in scripts.iter().enumerate() {
+                let script_path = match script.get("Path").and_then(Value::as_str) {
+                    Some(script_path) => script_path,
+                    None => {
+                        problems.push(format!("Script {} has no \"Path\"", index));
+                        continue;
+                    }
+                };
+                if !script.get("RunOn").map_or(false, Value::is_string) {
+                    problems.push(format!("Script \"{}\" has no \"RunOn\"", script_path));
+                }
+                if !file_exists(&mod_folder.join(SCRIPTS_FOLDER), script_path) {
+                    problems.push(format!(
+                        "Script \"{}\" doesn't exist in {}",
+                        script_path, SCRIPTS_FOLDER
+                    ));
+                }
+            }
+        }
+        Some(_) => problems.push("\"Scripts\" is not a list".to_string()),
+    }
+
+    match mod_json.get("Localisation") {
+        None => {}
+        Some(Value::Array(localisation_files)) => {
+            for localisation_file in localisation_files {
+                let localisation_path = match localisation_file.as_str() {
+                    Some(localisation_path) => localisation_path,
+                    None => {
+                        problems.push(format!(
+                            "Localisation entry {} is not a path",
+                            localisation_file
+                        ));
+                        continue;
+                    }
+                };
+                let resolved_path = localisation_path.replace("%language%", FALLBACK_LANGUAGE);
+                if !file_exists(&mod_folder.join(LOCALISATION_FOLDER), &resolved_path) {
+                    problems.push(format!(
+                        "Localisation file \"{}\" doesn't exist in {}",
+                        resolved_path, LOCALISATION_FOLDER
+                    ));
+                }
+            }
+        }
+        Some(_) => problems.push("\"Localisation\" is not a list".to_string()),
+    }
+
+    problems
+}
+
+/// Encoding of a text file, as far as it can be told from its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// The only encoding the game reads localisation files in
+    Utf16LeBom,
+    Utf16Le,
+    Utf16BeBom,
+    Utf8Bom,
+    Utf8,
+    Unknown,
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextEncoding::Utf16LeBom => write!(f, "UTF-16 LE with BOM"),
+            TextEncoding::Utf16Le => write!(f, "UTF-16 LE without BOM"),
+            TextEncoding::Utf16BeBom => write!(f, "UTF-16 BE with BOM"),
+            TextEncoding::Utf8Bom => write!(f, "UTF-8 with BOM"),
+            TextEncoding::Utf8 => write!(f, "UTF-8"),
+            TextEncoding::Unknown => write!(f, "an unknown encoding"),
+        }
+    }
+}
+
+fn detect_encoding(bytes: &[u8]) -> TextEncoding {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        TextEncoding::Utf16LeBom
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+        TextEncoding::Utf16BeBom
+    } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        TextEncoding::Utf8Bom
+    // Mostly ASCII text in UTF-16 LE has a zero at every odd byte
+    } else if bytes.len() >= 2 && bytes.len() % 2 == 0 && bytes[1] == 0 && bytes[0] != 0 {
+        TextEncoding::Utf16Le
+    } else if std::str::from_utf8(bytes).is_ok() {
+        TextEncoding::Utf8
+    } else {
+        TextEncoding::Unknown
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> Option<String> {
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| to_u16([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Text of a file in the given encoding, without BOM
+fn decode(bytes: &[u8], encoding: TextEncoding) -> Option<String> {
+    match encoding {
+        TextEncoding::Utf16LeBom => decode_utf16(&bytes[2..], u16::from_le_bytes),
+        TextEncoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        TextEncoding::Utf16BeBom => decode_utf16(&bytes[2..], u16::from_be_bytes),
+        TextEncoding::Utf8Bom => String::from_utf8(bytes[3..].to_vec()).ok(),
+        TextEncoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+        TextEncoding::Unknown => None,
+    }
+}
+
+/// Localisation files of the mod in `mod_folder`, i.e. `resource/*localisation*.txt`
+fn localisation_files(mod_folder: &Path) -> Vec<PathBuf> {
+    let resource_folder = mod_folder.join(LOCALISATION_FOLDER).join("resource");
+    let entries = match fs::read_dir(resource_folder) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut localisation_files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_lowercase();
+            file_name.contains("localisation") && file_name.ends_with(".txt")
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    localisation_files.sort();
+    localisation_files
+}
+
+/// Localisation file that isn't encoded the way the game expects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingProblem {
+    pub path: PathBuf,
+    pub encoding: TextEncoding,
+}
+
+/// Finds localisation files of the mod in `mod_folder` that aren't UTF-16 LE with BOM
+///
+/// The game silently shows broken strings for those.
+pub fn check_localisation_encodings(mod_folder: &Path) -> Vec<EncodingProblem> {
+    let mut problems = vec![];
+    for path in localisation_files(mod_folder) {
+        let encoding = match fs::read(&path) {
+            Ok(bytes) => detect_encoding(&bytes),
+            Err(err) => {
+                println!("Couldn't read {}: {}", path.display(), err);
+                TextEncoding::Unknown
+            }
+        };
+        if encoding != TextEncoding::Utf16LeBom {
+            problems.push(EncodingProblem { path, encoding });
+        }
+    }
+    problems
+}
+
+/// Rewrites a localisation file as UTF-16 LE with BOM
+pub fn convert_to_utf16_le(path: &Path) -> Result<(), anyhow::Error> {
+    let bytes = fs::read(path)?;
+    let encoding = detect_encoding(&bytes);
+    let text = match decode(&bytes, encoding) {
+        Some(text) => text,
+        None => {
+            return Err(anyhow!(
+                "Can't convert \"{}\" from {}",
+                path.display(),
+                encoding
+            ))
+        }
+    };
+    let mut converted = vec![0xFF, 0xFE];
+    for unit in text.encode_utf16() {
+        converted.extend_from_slice(&unit.to_le_bytes());
+    }
+    fs::write(path, converted)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_comments_and_trailing_commas() {
+        let json = r#"{
+            // Shown in the mods menu
+            "Name": "Northstar.Client", /* inline */
+            "Description": "// not a comment, /* neither */",
+            "Scripts": [
+                { "Path": "a.nut", },
+            ],
+        }"#;
+
+        let value: Value = serde_json::from_str(&strip_json_extensions(json)).unwrap();
+
+        assert_eq!(value["Name"], "Northstar.Client");
+        assert_eq!(value["Description"], "// not a comment, /* neither */");
+        assert_eq!(value["Scripts"][0]["Path"], "a.nut");
+    }
+
+    #[test]
+    fn reports_missing_fields_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mod_folder = dir.path().join("Northstar.Client");
+        fs::create_dir_all(mod_folder.join("mod/scripts/vscripts/ui")).unwrap();
+        fs::create_dir_all(mod_folder.join("mod/resource")).unwrap();
+        fs::write(mod_folder.join("mod/scripts/vscripts/ui/Menu.nut"), "").unwrap();
+        fs::write(
+            mod_folder.join("mod/resource/northstar_client_localisation_english.txt"),
+            "",
+        )
+        .unwrap();
+        fs::write(
+            mod_folder.join("mod.json"),
+            r#"{
+                "Version": "1.0.0",
+                "Scripts": [
+                    { "Path": "ui/menu.nut", "RunOn": "UI" },
+                    { "Path": "ui/missing.nut", "RunOn": "UI" },
+                    { "RunOn": "UI" }
+                ],
+                "Localisation": [
+                    "resource/northstar_client_localisation_%language%.txt",
+                    "resource/missing_%language%.txt"
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate_mod(&mod_folder),
+            vec![
+                "mod.json has no \"Name\" text",
+                "Script \"ui/missing.nut\" doesn't exist in mod/scripts/vscripts",
+                "Script 2 has no \"Path\"",
+                "Localisation file \"resource/missing_english.txt\" doesn't exist in mod",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("mod.json"), "{ \"Name\": ").unwrap();
+
+        let problems = validate_mod(dir.path());
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("mod.json is not valid JSON"));
+    }
+
+    #[test]
+    fn finds_and_converts_badly_encoded_localisation_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let resource_folder = dir.path().join("mod/resource");
+        fs::create_dir_all(&resource_folder).unwrap();
+        let text = "\"Lang\"\n{\n\t\"NS_TÉST\" \"Tëst\"\n}\n";
+        let mut utf16 = vec![0xFF, 0xFE];
+        for unit in text.encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        fs::write(resource_folder.join("a_localisation_english.txt"), &utf16).unwrap();
+        fs::write(resource_folder.join("b_localisation_english.txt"), text).unwrap();
+        fs::write(resource_folder.join("readme.txt"), text).unwrap();
+
+        let problems = check_localisation_encodings(dir.path());
+
+        assert_eq!(
+            problems,
+            vec![EncodingProblem {
+                path: resource_folder.join("b_localisation_english.txt"),
+                encoding: TextEncoding::Utf8,
+            }]
+        );
+
+        convert_to_utf16_le(&problems[0].path).unwrap();
+
+        assert_eq!(fs::read(&problems[0].path).unwrap(), utf16);
+        assert!(check_localisation_encodings(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn detects_encodings() {
+        assert_eq!(detect_encoding(b"\xFF\xFEa\0"), TextEncoding::Utf16LeBom);
+        assert_eq!(detect_encoding(b"a\0b\0"), TextEncoding::Utf16Le);
+        assert_eq!(detect_encoding(b"\xFE\xFF\0a"), TextEncoding::Utf16BeBom);
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBFab"), TextEncoding::Utf8Bom);
+        assert_eq!(detect_encoding("äb".as_bytes()), TextEncoding::Utf8);
+        assert_eq!(detect_encoding(b"\xC3("), TextEncoding::Unknown);
+    }
+}